@@ -0,0 +1,351 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use voxel_engine_shader::{AmbientOcclusion, DebugView, PathTracing, Sky};
+
+pub const USAGE: &str = "\
+usage: voxel-engine [options] [model.vox]
+
+options:
+    --model <index>    show the model with the given index (default 0)
+    --scene            show all models placed by the scene graph
+    --traversal <name> octree traversal of the shader, stack or esvo (default stack)
+    --ao-samples <n>   ambient occlusion rays per pixel, 0 disables it (default 8)
+    --ao-radius <n>    maximum distance of occluders in voxels (default 4)
+    --path-trace       progressively render with the path tracer
+    --bounces <n>      maximum diffuse bounces of the path tracer (default 4)
+    --environment <file.hdr>
+                       light the scene with an equirectangular hdr image
+    --turbidity <n>    haziness of the physical sky from 2 to 10 (default 2.5)
+    --debug-view <name>
+                       show the stack traversal as shaded, iterations, depth, normals,
+                       level, node or stack, the v key cycles the views (default shaded)
+    --cpu              render a single frame on the cpu into the --output file
+    --headless         render a single frame with vulkan without a window into the
+                       --output file
+    --output <file.png>
+                       image file of the frame rendered by --cpu or --headless
+    --size <WxH>       size of the rendered frame in pixels (default 800x600)
+    --help             print this message
+
+controls:
+    left drag          orbit the camera around the model
+    middle drag        pan the camera
+    mouse wheel        move the camera towards the model, or change the fly speed
+    double-click       orbit around the voxel under the cursor
+    h                  frame the whole model
+    f                  toggle between the orbit and the fly camera
+    w, a, s, d, q, e   fly forward, left, back, right, down and up
+    shift              fly faster
+    v                  cycle the debug views";
+
+/// Names of the debug views, indexed by the `DebugView::*` modes
+pub const DEBUG_VIEWS: [&str; DebugView::COUNT as usize] = [
+    "shaded",
+    "iterations",
+    "depth",
+    "normals",
+    "level",
+    "node",
+    "stack",
+];
+
+/// Octree traversal used by the compute shader
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Traversal {
+    #[default]
+    Stack,
+    Esvo,
+}
+
+impl FromStr for Traversal {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "stack" => Ok(Traversal::Stack),
+            "esvo" => Ok(Traversal::Esvo),
+            _ => Err(format!("unknown traversal '{name}'")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    /// Vox file to show, the builtin octree is shown if not set
+    pub path: Option<PathBuf>,
+    pub model: usize,
+    pub scene: bool,
+    pub traversal: Traversal,
+    pub ambient_occlusion: AmbientOcclusion,
+    pub path_trace: bool,
+    pub path_tracing: PathTracing,
+    /// Hdr image replacing the physical sky
+    pub environment: Option<PathBuf>,
+    pub sky: Sky,
+    pub debug_view: DebugView,
+    /// Render a single frame with the CPU renderer instead of opening a window
+    pub cpu: bool,
+    /// Render a single frame with Vulkan instead of opening a window
+    pub headless: bool,
+    pub output: Option<PathBuf>,
+    /// Width and height of frames rendered without a window
+    pub size: (u32, u32),
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            path: None,
+            model: 0,
+            scene: false,
+            traversal: Traversal::default(),
+            ambient_occlusion: AmbientOcclusion::default(),
+            path_trace: false,
+            path_tracing: PathTracing::default(),
+            environment: None,
+            sky: Sky::default(),
+            debug_view: DebugView::default(),
+            cpu: false,
+            headless: false,
+            output: None,
+            size: (800, 600),
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut result = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--model" => {
+                    let index = args.next().ok_or("missing value for --model")?;
+                    result.model = index
+                        .parse()
+                        .map_err(|_| format!("invalid model index '{index}'"))?;
+                }
+                "--scene" => result.scene = true,
+                "--traversal" => {
+                    result.traversal = args
+                        .next()
+                        .ok_or("missing value for --traversal")?
+                        .parse()?;
+                }
+                "--ao-samples" => {
+                    let samples = args.next().ok_or("missing value for --ao-samples")?;
+                    result.ambient_occlusion.samples = samples
+                        .parse()
+                        .map_err(|_| format!("invalid sample count '{samples}'"))?;
+                }
+                "--ao-radius" => {
+                    let radius = args.next().ok_or("missing value for --ao-radius")?;
+                    result.ambient_occlusion.radius = radius
+                        .parse()
+                        .ok()
+                        .filter(|radius: &f32| *radius > 0.0)
+                        .ok_or_else(|| format!("invalid radius '{radius}'"))?;
+                }
+                "--path-trace" => result.path_trace = true,
+                "--bounces" => {
+                    let bounces = args.next().ok_or("missing value for --bounces")?;
+                    result.path_tracing.bounces = bounces
+                        .parse()
+                        .map_err(|_| format!("invalid bounce count '{bounces}'"))?;
+                }
+                "--environment" => {
+                    let path = args.next().ok_or("missing value for --environment")?;
+                    result.environment = Some(PathBuf::from(path));
+                }
+                "--turbidity" => {
+                    let turbidity = args.next().ok_or("missing value for --turbidity")?;
+                    result.sky.turbidity = turbidity
+                        .parse()
+                        .ok()
+                        .filter(|turbidity: &f32| (1.0..=20.0).contains(turbidity))
+                        .ok_or_else(|| format!("invalid turbidity '{turbidity}'"))?;
+                }
+                "--debug-view" => {
+                    let name = args.next().ok_or("missing value for --debug-view")?;
+                    let mode = DEBUG_VIEWS
+                        .iter()
+                        .position(|view| *view == name)
+                        .ok_or_else(|| format!("unknown debug view '{name}'"))?;
+                    result.debug_view.mode = mode as u32;
+                }
+                "--cpu" => result.cpu = true,
+                "--headless" => result.headless = true,
+                "--output" => {
+                    let path = args.next().ok_or("missing value for --output")?;
+                    result.output = Some(PathBuf::from(path));
+                }
+                "--size" => {
+                    let size = args.next().ok_or("missing value for --size")?;
+                    result.size = size
+                        .split_once('x')
+                        .and_then(|(width, height)| width.parse().ok().zip(height.parse().ok()))
+                        .filter(|(width, height)| *width > 0 && *height > 0)
+                        .ok_or_else(|| format!("invalid size '{size}'"))?;
+                }
+                "--help" | "-h" => result.help = true,
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{arg}'")),
+                _ if result.path.is_some() => return Err(format!("unexpected argument '{arg}'")),
+                _ => result.path = Some(PathBuf::from(arg)),
+            }
+        }
+
+        if result.scene && result.model != 0 {
+            return Err("--model can not be combined with --scene".to_string());
+        }
+
+        if result.debug_view.mode != DebugView::SHADED && !result.supports_debug_views() {
+            return Err("--debug-view needs the stack traversal without --path-trace".to_string());
+        }
+
+        let offscreen = result.cpu || result.headless;
+        if result.cpu && result.headless {
+            return Err("--cpu can not be combined with --headless".to_string());
+        }
+
+        if offscreen && result.output.is_none() {
+            return Err("rendering without a window needs an --output file".to_string());
+        }
+
+        if offscreen && result.path_trace {
+            return Err(
+                "rendering without a window can not be combined with --path-trace".to_string(),
+            );
+        }
+
+        if result.output.is_some() && !offscreen {
+            return Err("--output needs --cpu or --headless".to_string());
+        }
+
+        Ok(result)
+    }
+
+    /// Debug views are only rendered by the entry point of the stack traversal
+    pub fn supports_debug_views(&self) -> bool {
+        self.traversal == Traversal::Stack && !self.path_trace
+    }
+}
+
+#[test]
+fn test_parse_args() {
+    let parse = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+
+    assert_eq!(parse(&[]), Ok(Args::default()));
+    assert_eq!(
+        parse(&["--model", "2", "monu3.vox"]),
+        Ok(Args {
+            path: Some(PathBuf::from("monu3.vox")),
+            model: 2,
+            ..Args::default()
+        })
+    );
+    assert_eq!(
+        parse(&["monu3.vox", "--scene"]),
+        Ok(Args {
+            path: Some(PathBuf::from("monu3.vox")),
+            scene: true,
+            ..Args::default()
+        })
+    );
+
+    assert_eq!(
+        parse(&["--traversal", "esvo"]),
+        Ok(Args {
+            traversal: Traversal::Esvo,
+            ..Args::default()
+        })
+    );
+
+    assert_eq!(
+        parse(&["--ao-samples", "16", "--ao-radius", "2.5"]),
+        Ok(Args {
+            ambient_occlusion: AmbientOcclusion {
+                samples: 16,
+                radius: 2.5
+            },
+            ..Args::default()
+        })
+    );
+
+    assert_eq!(
+        parse(&["--path-trace", "--bounces", "2"]),
+        Ok(Args {
+            path_trace: true,
+            path_tracing: PathTracing {
+                frame: 0,
+                bounces: 2
+            },
+            ..Args::default()
+        })
+    );
+
+    assert_eq!(
+        parse(&["--environment", "sky.hdr", "--turbidity", "4"]),
+        Ok(Args {
+            environment: Some(PathBuf::from("sky.hdr")),
+            sky: Sky {
+                turbidity: 4.0,
+                ..Sky::default()
+            },
+            ..Args::default()
+        })
+    );
+
+    assert_eq!(
+        parse(&["--debug-view", "stack"]),
+        Ok(Args {
+            debug_view: DebugView {
+                mode: DebugView::STACK_DEPTH
+            },
+            ..Args::default()
+        })
+    );
+
+    assert_eq!(
+        parse(&["--cpu", "--output", "frame.png", "--size", "64x32"]),
+        Ok(Args {
+            cpu: true,
+            output: Some(PathBuf::from("frame.png")),
+            size: (64, 32),
+            ..Args::default()
+        })
+    );
+
+    assert_eq!(
+        parse(&["--headless", "--output", "frame.png"]),
+        Ok(Args {
+            headless: true,
+            output: Some(PathBuf::from("frame.png")),
+            ..Args::default()
+        })
+    );
+
+    assert!(parse(&["--model"]).is_err());
+    assert!(parse(&["--traversal", "bvh"]).is_err());
+    assert!(parse(&["--model", "first"]).is_err());
+    assert!(parse(&["--ao-samples", "-1"]).is_err());
+    assert!(parse(&["--ao-radius", "0"]).is_err());
+    assert!(parse(&["--bounces", "many"]).is_err());
+    assert!(parse(&["--turbidity", "0.5"]).is_err());
+    assert!(parse(&["--environment"]).is_err());
+    assert!(parse(&["--debug-view", "wireframe"]).is_err());
+    assert!(parse(&["--debug-view", "depth", "--path-trace"]).is_err());
+    assert!(parse(&["--debug-view", "depth", "--traversal", "esvo"]).is_err());
+    assert!(parse(&["--scene", "--model", "1"]).is_err());
+    assert!(parse(&["--cpu"]).is_err());
+    assert!(parse(&["--cpu", "--output", "a.png", "--path-trace"]).is_err());
+    assert!(parse(&["--output", "a.png"]).is_err());
+    assert!(parse(&["--headless"]).is_err());
+    assert!(parse(&["--headless", "--cpu", "--output", "a.png"]).is_err());
+    assert!(parse(&["--size", "64"]).is_err());
+    assert!(parse(&["--size", "0x32"]).is_err());
+    assert!(parse(&["--fast"]).is_err());
+    assert!(parse(&["a.vox", "b.vox"]).is_err());
+}
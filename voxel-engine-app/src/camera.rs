@@ -1,8 +1,13 @@
 use std::f32::consts::PI;
-use voxel_engine_shader::glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
-use voxel_engine_shader::CameraMatrices;
+use voxel_engine_shader::glam::{Mat3, Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
+use voxel_engine_shader::{CameraMatrices, Octree, Ray};
 use winit::dpi::{LogicalSize, PhysicalPosition};
 
+/// Vertical field of view in radians
+const FOV_Y: f32 = PI / 4.0;
+/// Closest distance the camera can dolly to its target
+const MIN_DISTANCE: f32 = 0.01;
+
 pub struct Camera {
     pub position: Vec3,
     pub target: Vec3,
@@ -15,12 +20,8 @@ impl Camera {
     pub fn new(position: Vec3, target: Vec3, screen_size: LogicalSize<f32>) -> Self {
         let up = Vec3::new(0.0, -1.0, 0.0);
         let view = Mat4::look_at_rh(position, target, up);
-        let projection = Mat4::perspective_rh(
-            45.0_f32.to_radians(),
-            screen_size.width / screen_size.height,
-            0.1,
-            100.0,
-        );
+        let projection =
+            Mat4::perspective_rh(FOV_Y, screen_size.width / screen_size.height, 0.1, 100.0);
 
         Self {
             position,
@@ -31,17 +32,24 @@ impl Camera {
         }
     }
 
+    /// Camera the app and the frames rendered without a window start with, looking at the
+    /// whole model from the direction of the negative axes
+    pub fn initial(octree: &Octree, screen_size: LogicalSize<f32>) -> Self {
+        let mut camera = Self::new(Vec3::splat(-3.0), Vec3::ZERO, screen_size);
+        if let Some((min, max)) = octree.bounds() {
+            camera.frame(min, max);
+        }
+
+        camera
+    }
+
     fn update_view(&mut self) {
         self.view = Mat4::look_at_rh(self.position, self.target, self.up);
     }
 
     pub fn update_projection(&mut self, screen_size: LogicalSize<f32>) {
-        self.projection = Mat4::perspective_rh(
-            45.0_f32.to_radians(),
-            screen_size.width / screen_size.height,
-            0.1,
-            100.0,
-        );
+        self.projection =
+            Mat4::perspective_rh(FOV_Y, screen_size.width / screen_size.height, 0.1, 100.0);
     }
 
     pub fn matrices(&self) -> CameraMatrices {
@@ -76,4 +84,181 @@ impl Camera {
         self.position = position.xyz();
         self.update_view();
     }
+
+    /// Move towards the target, scaling the distance to it by `factor`
+    pub fn dolly(&mut self, factor: f32) {
+        let offset = self.position - self.target;
+        let distance = (offset.length() * factor).max(MIN_DISTANCE);
+
+        self.position = self.target + offset.normalize() * distance;
+        self.update_view();
+    }
+
+    /// Move the camera with its target along the screen, so the target follows the cursor
+    pub fn pan(&mut self, delta: PhysicalPosition<f32>, screen_size: LogicalSize<f32>) {
+        // World units per pixel at the distance of the target
+        let distance = (self.position - self.target).length();
+        let scale = 2.0 * distance * (FOV_Y / 2.0).tan() / screen_size.height;
+
+        let right = self.view.transpose().col(0).xyz();
+        let down = self.view.transpose().col(1).xyz();
+        let translation = -(right * delta.x + down * delta.y) * scale;
+
+        self.position += translation;
+        self.target += translation;
+        self.update_view();
+    }
+
+    /// Orbit around a new target, keeping the camera position
+    pub fn focus(&mut self, target: Vec3) {
+        self.target = target;
+        self.update_view();
+    }
+
+    /// Look at the center of a box from the current direction, close enough to fit the box
+    pub fn frame(&mut self, min: Vec3, max: Vec3) {
+        let center = (min + max) / 2.0;
+        let radius = (max - min).length() / 2.0;
+
+        // The narrower of the vertical and horizontal field of view has to fit the box
+        let half_fov = (FOV_Y / 2.0)
+            .tan()
+            .min(1.0 / self.projection.x_axis.x)
+            .atan();
+        let distance = (radius / half_fov.sin()).max(MIN_DISTANCE);
+
+        let direction = (self.position - self.target).normalize();
+        self.target = center;
+        self.position = center + direction * distance;
+        self.update_view();
+    }
+
+    /// Ray through a cursor position in pixels
+    pub fn cursor_ray(
+        &self,
+        position: PhysicalPosition<f32>,
+        screen_size: LogicalSize<f32>,
+    ) -> Ray {
+        let screen_coords = Vec2::new(
+            position.x / screen_size.width,
+            position.y / screen_size.height,
+        ) * 2.0
+            - 1.0;
+        self.matrices().create_ray(screen_coords)
+    }
+
+    /// Turn the view around the camera position, keeping the distance to the target
+    pub fn fly_look(&mut self, delta: PhysicalPosition<f32>, sensitivity: f32) {
+        let offset = self.target - self.position;
+        let right = self.view.transpose().col(0).xyz();
+
+        let yaw = Mat3::from_axis_angle(-self.up, delta.x * sensitivity);
+        let mut pitch = Mat3::from_axis_angle(right, delta.y * sensitivity);
+
+        // Stop before looking straight up or down, where the view flips
+        const MAX_ANGLE: f32 = 0.99;
+        if (pitch * offset).normalize().dot(self.up).abs() > MAX_ANGLE {
+            pitch = Mat3::IDENTITY;
+        }
+
+        self.target = self.position + yaw * pitch * offset;
+        self.update_view();
+    }
+
+    /// Move the camera with its target, `movement` has x pointing right, y up and z forward
+    pub fn fly_move(&mut self, movement: Vec3) {
+        let right = self.view.transpose().col(0).xyz();
+        let forward = (self.target - self.position).normalize();
+        let translation = right * movement.x - self.up * movement.y + forward * movement.z;
+
+        self.position += translation;
+        self.target += translation;
+        self.update_view();
+    }
+}
+
+#[test]
+fn test_fly_camera() {
+    let mut camera = Camera::new(
+        Vec3::new(0.0, 0.0, -3.0),
+        Vec3::ZERO,
+        LogicalSize::new(4.0, 3.0),
+    );
+
+    // Forward moves towards the target, right and up match the screen axes of the view
+    camera.fly_move(Vec3::new(0.0, 0.0, 1.0));
+    assert!((camera.position - Vec3::new(0.0, 0.0, -2.0)).length() < 1e-6);
+    assert!((camera.target - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+
+    camera.fly_move(Vec3::new(1.0, 1.0, 0.0));
+    let view_position = camera.view * Vec4::new(0.0, 0.0, 1.0, 1.0);
+    assert!(view_position.x < 0.0, "{view_position}");
+    assert!((camera.position.y - 1.0).abs() < 1e-6);
+
+    // Looking right turns towards the right of the screen
+    let right = camera.view.transpose().col(0).xyz();
+    let distance = (camera.target - camera.position).length();
+    camera.fly_look(PhysicalPosition::new(100.0, 0.0), 0.001);
+    assert!((camera.target - camera.position).dot(right) > 0.0);
+    assert!(((camera.target - camera.position).length() - distance).abs() < 1e-5);
+
+    // Looking up stops before the view flips
+    camera.fly_look(PhysicalPosition::new(0.0, -100_000.0), 0.001);
+    camera.fly_look(PhysicalPosition::new(0.0, -100.0), 0.001);
+    let forward = (camera.target - camera.position).normalize();
+    assert!(forward.dot(camera.up).abs() <= 0.99, "{forward}");
+}
+
+#[test]
+fn test_orbit_camera() {
+    use voxel_engine_shader::glam::IVec3;
+
+    let screen_size = LogicalSize::new(400.0, 300.0);
+    let mut camera = Camera::new(Vec3::new(0.0, 0.0, -3.0), Vec3::ZERO, screen_size);
+
+    // Dollying scales the distance along the view direction
+    camera.dolly(0.5);
+    assert!((camera.position - Vec3::new(0.0, 0.0, -1.5)).length() < 1e-6);
+    camera.dolly(0.0);
+    assert!((camera.position.length() - MIN_DISTANCE).abs() < 1e-6);
+    camera.dolly(300.0);
+
+    // The target follows the cursor when panning
+    let center = PhysicalPosition::new(200.0, 150.0);
+    camera.pan(PhysicalPosition::new(40.0, -30.0), screen_size);
+    let target = camera.projection * camera.view * Vec4::from((Vec3::ZERO, 1.0));
+    let screen = (target.xy() / target.w + 1.0) / 2.0 * Vec2::new(400.0, 300.0);
+    assert!(
+        (screen - Vec2::new(240.0, 120.0)).length() < 1e-2,
+        "{screen}"
+    );
+    assert!((camera.position - camera.target - Vec3::new(0.0, 0.0, -3.0)).length() < 1e-5);
+
+    // The cursor ray at the screen center points at the target
+    let ray = camera.cursor_ray(center, screen_size);
+    assert!((ray.origin - camera.position).length() < 1e-5);
+    assert!(ray.direction.normalize().dot(Vec3::Z) > 0.9999);
+
+    camera.focus(Vec3::new(1.0, 0.0, 0.0));
+    assert_eq!(camera.target, Vec3::new(1.0, 0.0, 0.0));
+
+    // Framing a box keeps the view direction and fits the box
+    let direction = (camera.position - camera.target).normalize();
+    camera.frame(Vec3::splat(-0.5), Vec3::splat(0.5));
+    assert_eq!(camera.target, Vec3::ZERO);
+    assert!((camera.position.normalize() - direction).length() < 1e-5);
+    let distance = camera.position.length();
+    assert!(distance > 0.75_f32.sqrt() && distance < 4.0, "{distance}");
+
+    // The initial camera frames the model
+    let octree = Octree::from_voxels([IVec3::ZERO, IVec3::new(3, 1, 0)]).unwrap();
+    let (min, max) = octree.bounds().unwrap();
+    let camera = Camera::initial(&octree, screen_size);
+    assert!((camera.target - (min + max) / 2.0).length() < 1e-6);
+    assert!(
+        (camera.position - camera.target)
+            .normalize()
+            .dot(-Vec3::ONE.normalize())
+            > 0.9999
+    );
 }
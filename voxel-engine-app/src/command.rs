@@ -1,22 +1,28 @@
 use std::sync::Arc;
+use voxel_engine_shader::glam::Vec4;
+use voxel_engine_shader::DebugView;
+use vulkano::buffer::Subbuffer;
 use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
 use vulkano::command_buffer::{
-    AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage, PrimaryAutoCommandBuffer,
+    AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage, CopyImageToBufferInfo,
+    PrimaryAutoCommandBuffer,
 };
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAlloc;
 use vulkano::descriptor_set::PersistentDescriptorSet;
 use vulkano::device::{Device, Queue};
-use vulkano::image::{ImageAccess, StorageImage, SwapchainImage};
+use vulkano::image::{ImageAccess, ImageDimensions, StorageImage, SwapchainImage};
 use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
 
+#[allow(clippy::too_many_arguments)]
 pub fn record_command_buffers(
     _device: &Arc<Device>,
     queue: &Arc<Queue>,
     pipeline: &Arc<ComputePipeline>,
-    images: &Vec<Arc<SwapchainImage>>,
+    images: &[Arc<SwapchainImage>],
     command_buffer_allocator: &StandardCommandBufferAllocator,
     compute_image_set: &Arc<PersistentDescriptorSet<StandardDescriptorSetAlloc>>,
     compute_image: &Arc<StorageImage>,
+    debug_view: DebugView,
 ) -> Vec<Arc<PrimaryAutoCommandBuffer>> {
     images
         .iter()
@@ -28,20 +34,15 @@ pub fn record_command_buffers(
             )
             .unwrap();
 
+            record_dispatch(
+                &mut builder,
+                pipeline,
+                compute_image_set,
+                swapchain_image.dimensions(),
+                debug_view,
+            );
+
             builder
-                .bind_pipeline_compute(pipeline.clone())
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Compute,
-                    pipeline.layout().clone(),
-                    0,
-                    compute_image_set.clone(),
-                )
-                .dispatch([
-                    swapchain_image.dimensions().width().div_ceil(16),
-                    swapchain_image.dimensions().height().div_ceil(16),
-                    1,
-                ])
-                .unwrap()
                 .blit_image(BlitImageInfo::images(
                     compute_image.clone(),
                     swapchain_image.clone(),
@@ -52,3 +53,68 @@ pub fn record_command_buffers(
         })
         .collect()
 }
+
+/// Render a single frame into the compute image and copy it into the output buffer
+pub fn record_offscreen_command_buffer(
+    queue: &Arc<Queue>,
+    pipeline: &Arc<ComputePipeline>,
+    command_buffer_allocator: &StandardCommandBufferAllocator,
+    compute_image_set: &Arc<PersistentDescriptorSet<StandardDescriptorSetAlloc>>,
+    compute_image: &Arc<StorageImage>,
+    debug_view: DebugView,
+    output_buffer: &Subbuffer<[Vec4]>,
+) -> PrimaryAutoCommandBuffer {
+    let mut builder = AutoCommandBufferBuilder::primary(
+        command_buffer_allocator,
+        queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    record_dispatch(
+        &mut builder,
+        pipeline,
+        compute_image_set,
+        compute_image.dimensions(),
+        debug_view,
+    );
+
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            compute_image.clone(),
+            output_buffer.clone(),
+        ))
+        .unwrap();
+
+    builder.build().unwrap()
+}
+
+fn record_dispatch(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: &Arc<ComputePipeline>,
+    compute_image_set: &Arc<PersistentDescriptorSet<StandardDescriptorSetAlloc>>,
+    dimensions: ImageDimensions,
+    debug_view: DebugView,
+) {
+    builder
+        .bind_pipeline_compute(pipeline.clone())
+        .bind_descriptor_sets(
+            PipelineBindPoint::Compute,
+            pipeline.layout().clone(),
+            0,
+            compute_image_set.clone(),
+        );
+
+    // Only the entry point with debug views has push constants
+    if !pipeline.layout().push_constant_ranges().is_empty() {
+        builder.push_constants(pipeline.layout().clone(), 0, debug_view);
+    }
+
+    builder
+        .dispatch([
+            dimensions.width().div_ceil(16),
+            dimensions.height().div_ceil(16),
+            1,
+        ])
+        .unwrap();
+}
@@ -1,7 +1,14 @@
 use crate::allocators::Allocators;
+use crate::args::{Args, Traversal};
+use crate::hdr::HdrImage;
+use crate::loader::Model;
 use std::sync::Arc;
-use voxel_engine_shader::{CameraMatrices, OctreeNode};
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use voxel_engine_shader::glam::Vec4;
+use voxel_engine_shader::{
+    AmbientOcclusion, CameraMatrices, Highlight, Material, OctreeNode, PathTracing, Sky, Sun,
+    VoxelAttribute,
+};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAlloc;
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::{Device, Queue};
@@ -18,12 +25,30 @@ use winit::dpi::PhysicalSize;
 const SHADER_BYTES: &[u8] = include_bytes!(env!("voxel_engine_shader.spv"));
 
 type OctreeBuffer = Subbuffer<[OctreeNode]>;
+type AttributeBuffer = Subbuffer<[VoxelAttribute]>;
+type MaterialBuffer = Subbuffer<[Material]>;
 type CameraBuffer = Subbuffer<CameraMatrices>;
+type SunBuffer = Subbuffer<Sun>;
+type SkyBuffer = Subbuffer<Sky>;
+type EnvironmentBuffer = Subbuffer<[Vec4]>;
+type AmbientOcclusionBuffer = Subbuffer<AmbientOcclusion>;
+type PathTracingBuffer = Subbuffer<PathTracing>;
+type AccumulationBuffer = Subbuffer<[Vec4]>;
+type HighlightBuffer = Subbuffer<Highlight>;
 
 pub struct Compute {
     pub pipeline: Arc<ComputePipeline>,
     pub camera_buffer: CameraBuffer,
+    pub sun_buffer: SunBuffer,
+    pub sky_buffer: SkyBuffer,
+    pub environment_buffer: EnvironmentBuffer,
+    pub ambient_occlusion_buffer: AmbientOcclusionBuffer,
+    pub path_tracing_buffer: PathTracingBuffer,
+    pub accumulation_buffer: AccumulationBuffer,
+    pub highlight_buffer: HighlightBuffer,
     pub octree_buffer: OctreeBuffer,
+    pub attribute_buffer: AttributeBuffer,
+    pub material_buffer: MaterialBuffer,
     pub render_image: Arc<StorageImage>,
     pub render_image_view: Arc<ImageView<StorageImage>>,
     pub render_image_set: Arc<PersistentDescriptorSet<StandardDescriptorSetAlloc>>,
@@ -34,13 +59,33 @@ impl Compute {
         device: &Arc<Device>,
         queue: &Arc<Queue>,
         screen_size: PhysicalSize<u32>,
-        octree: Vec<OctreeNode>,
+        model: &Model,
+        environment: Option<&HdrImage>,
+        args: &Args,
         allocators: &Allocators,
     ) -> Self {
         let shader = create_shader(device);
-        let pipeline = create_pipeline(device, shader);
+        let pipeline = create_pipeline(device, shader, args.traversal, args.path_trace);
         let camera_buffer = create_camera_buffer(allocators);
-        let octree_buffer = create_octree_buffer(octree, allocators);
+        let sun_buffer = create_sun_buffer(allocators);
+        let sky = environment.map_or(args.sky, HdrImage::sky);
+        let sky_buffer = create_sky_buffer(sky, allocators);
+        let environment_buffer = create_storage_buffer(
+            environment.map_or(Vec::new(), |image| image.pixels.clone()),
+            allocators,
+        );
+        let ambient_occlusion_buffer =
+            create_ambient_occlusion_buffer(args.ambient_occlusion, allocators);
+        let path_tracing_buffer = create_path_tracing_buffer(args.path_tracing, allocators);
+        let accumulation_buffer = create_storage_buffer(
+            vec![Vec4::ZERO; (screen_size.width * screen_size.height) as usize],
+            allocators,
+        );
+        let highlight_buffer = create_highlight_buffer(allocators);
+        let octree = &model.octree;
+        let octree_buffer = create_storage_buffer(octree.nodes.clone(), allocators);
+        let attribute_buffer = create_storage_buffer(octree.attributes.clone(), allocators);
+        let material_buffer = create_storage_buffer(model.materials.clone(), allocators);
         let render_image = create_render_image(queue, screen_size, allocators);
         let render_image_view = create_render_image_view(&render_image);
         let render_image_set = create_render_image_set(
@@ -48,13 +93,31 @@ impl Compute {
             &render_image_view,
             &camera_buffer,
             &octree_buffer,
+            &attribute_buffer,
+            &material_buffer,
+            &sun_buffer,
+            &sky_buffer,
+            &environment_buffer,
+            &ambient_occlusion_buffer,
+            &path_tracing_buffer,
+            &accumulation_buffer,
+            &highlight_buffer,
             allocators,
         );
 
         Self {
             pipeline,
             camera_buffer,
+            sun_buffer,
+            sky_buffer,
+            environment_buffer,
+            ambient_occlusion_buffer,
+            path_tracing_buffer,
+            accumulation_buffer,
+            highlight_buffer,
             octree_buffer,
+            attribute_buffer,
+            material_buffer,
             render_image,
             render_image_view,
             render_image_set,
@@ -66,10 +129,22 @@ fn create_shader(device: &Arc<Device>) -> Arc<ShaderModule> {
     unsafe { ShaderModule::from_bytes(device.clone(), SHADER_BYTES) }.unwrap()
 }
 
-fn create_pipeline(device: &Arc<Device>, shader: Arc<ShaderModule>) -> Arc<ComputePipeline> {
+fn create_pipeline(
+    device: &Arc<Device>,
+    shader: Arc<ShaderModule>,
+    traversal: Traversal,
+    path_trace: bool,
+) -> Arc<ComputePipeline> {
+    let entry_point = match (traversal, path_trace) {
+        (Traversal::Stack, false) => "main_cs",
+        (Traversal::Esvo, false) => "main_cs_esvo",
+        (Traversal::Stack, true) => "main_cs_path_trace",
+        (Traversal::Esvo, true) => "main_cs_path_trace_esvo",
+    };
+
     ComputePipeline::new(
         device.clone(),
-        shader.entry_point("main_cs").unwrap(),
+        shader.entry_point(entry_point).unwrap(),
         &(),
         None,
         |_| {},
@@ -93,7 +168,101 @@ fn create_camera_buffer(allocators: &Allocators) -> CameraBuffer {
     .unwrap()
 }
 
-fn create_octree_buffer(octree: Vec<OctreeNode>, allocators: &Allocators) -> OctreeBuffer {
+fn create_sun_buffer(allocators: &Allocators) -> SunBuffer {
+    Buffer::from_data(
+        &allocators.memory,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        Sun::default(),
+    )
+    .unwrap()
+}
+
+fn create_sky_buffer(sky: Sky, allocators: &Allocators) -> SkyBuffer {
+    Buffer::from_data(
+        &allocators.memory,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        sky,
+    )
+    .unwrap()
+}
+
+fn create_ambient_occlusion_buffer(
+    ambient_occlusion: AmbientOcclusion,
+    allocators: &Allocators,
+) -> AmbientOcclusionBuffer {
+    Buffer::from_data(
+        &allocators.memory,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        ambient_occlusion,
+    )
+    .unwrap()
+}
+
+fn create_path_tracing_buffer(
+    path_tracing: PathTracing,
+    allocators: &Allocators,
+) -> PathTracingBuffer {
+    Buffer::from_data(
+        &allocators.memory,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        path_tracing,
+    )
+    .unwrap()
+}
+
+fn create_highlight_buffer(allocators: &Allocators) -> HighlightBuffer {
+    Buffer::from_data(
+        &allocators.memory,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        Highlight::default(),
+    )
+    .unwrap()
+}
+
+fn create_storage_buffer<T: BufferContents + Default>(
+    mut data: Vec<T>,
+    allocators: &Allocators,
+) -> Subbuffer<[T]> {
+    // Empty buffers can not be created
+    if data.is_empty() {
+        data.push(T::default());
+    }
+
     Buffer::from_iter(
         &allocators.memory,
         BufferCreateInfo {
@@ -104,7 +273,7 @@ fn create_octree_buffer(octree: Vec<OctreeNode>, allocators: &Allocators) -> Oct
             usage: MemoryUsage::Upload,
             ..Default::default()
         },
-        octree.into_iter(),
+        data.into_iter(),
     )
     .unwrap()
 }
@@ -144,11 +313,21 @@ fn create_render_image_view(render_image: &Arc<StorageImage>) -> Arc<ImageView<S
     .unwrap()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_render_image_set(
     pipeline: &Arc<ComputePipeline>,
     render_image_view: &Arc<ImageView<StorageImage>>,
     camera_buffer: &CameraBuffer,
     octree_buffer: &OctreeBuffer,
+    attribute_buffer: &AttributeBuffer,
+    material_buffer: &MaterialBuffer,
+    sun_buffer: &SunBuffer,
+    sky_buffer: &SkyBuffer,
+    environment_buffer: &EnvironmentBuffer,
+    ambient_occlusion_buffer: &AmbientOcclusionBuffer,
+    path_tracing_buffer: &PathTracingBuffer,
+    accumulation_buffer: &AccumulationBuffer,
+    highlight_buffer: &HighlightBuffer,
     allocators: &Allocators,
 ) -> Arc<PersistentDescriptorSet<StandardDescriptorSetAlloc>> {
     let pipeline_layout = pipeline.layout().set_layouts().get(0).unwrap();
@@ -157,6 +336,15 @@ fn create_render_image_set(
         WriteDescriptorSet::image_view(0, render_image_view.clone()),
         WriteDescriptorSet::buffer(1, camera_buffer.clone()),
         WriteDescriptorSet::buffer(2, octree_buffer.clone()),
+        WriteDescriptorSet::buffer(3, attribute_buffer.clone()),
+        WriteDescriptorSet::buffer(4, sun_buffer.clone()),
+        WriteDescriptorSet::buffer(5, ambient_occlusion_buffer.clone()),
+        WriteDescriptorSet::buffer(6, path_tracing_buffer.clone()),
+        WriteDescriptorSet::buffer(7, accumulation_buffer.clone()),
+        WriteDescriptorSet::buffer(8, material_buffer.clone()),
+        WriteDescriptorSet::buffer(9, environment_buffer.clone()),
+        WriteDescriptorSet::buffer(10, sky_buffer.clone()),
+        WriteDescriptorSet::buffer(11, highlight_buffer.clone()),
     ];
 
     let available_bindings = pipeline_layout
@@ -35,10 +35,10 @@ impl Context {
         .expect("Failed to create instance");
 
         let surface = window_builder
-            .build_vk_surface(&event_loop, instance.clone())
+            .build_vk_surface(event_loop, instance.clone())
             .unwrap();
 
-        let gpu_instance = find_gpu_model(&instance, &surface);
+        let gpu_instance = find_gpu_model(&instance, Some(&surface));
 
         Self {
             instance,
@@ -55,3 +55,11 @@ impl Context {
             .unwrap()
     }
 }
+
+/// Find a Vulkan device for rendering into offscreen images, without a window or surface
+pub fn create_headless_gpu() -> GpuModel {
+    let library = VulkanLibrary::new().expect("Failed to load vulkan library");
+    let instance = Instance::new(library, Default::default()).expect("Failed to create instance");
+
+    find_gpu_model(&instance, None)
+}
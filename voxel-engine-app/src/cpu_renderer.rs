@@ -0,0 +1,120 @@
+use crate::args::{Args, Traversal};
+use crate::hdr::HdrImage;
+use crate::loader::Model;
+use crate::render_image::RenderImage;
+use rayon::prelude::*;
+use voxel_engine_shader::glam::{uvec2, Vec4};
+use voxel_engine_shader::{
+    debug_pixel, shade_pixel, trace_octree_esvo, trace_octree_hit, CameraMatrices, DebugView,
+    Highlight, OctreeHit, OctreeNode, OctreeTraversal, Ray, Sun,
+};
+
+/// The CPU picks the traversal at runtime, where the shader has an entry point per traversal
+impl OctreeTraversal for Traversal {
+    fn trace(self, ray: &Ray, octree: &[OctreeNode]) -> OctreeHit {
+        match self {
+            Traversal::Stack => trace_octree_hit(ray, octree),
+            Traversal::Esvo => trace_octree_esvo(ray, octree),
+        }
+    }
+}
+
+/// Render a frame on the CPU with the per pixel code of the compute shader, rows are rendered
+/// in parallel. The result is linear like the render image of the GPU.
+pub fn render_cpu(
+    model: &Model,
+    environment: Option<&HdrImage>,
+    args: &Args,
+    camera: &CameraMatrices,
+    width: u32,
+    height: u32,
+) -> RenderImage {
+    let sky = environment.map_or(args.sky, HdrImage::sky);
+    let environment = environment.map_or(&[][..], |image| &image.pixels[..]);
+    let sun = Sun::default();
+    let octree = &model.octree;
+    let screen_size = uvec2(width, height);
+
+    let mut pixels = vec![Vec4::ZERO; (width * height) as usize];
+    pixels
+        .par_chunks_mut(width as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let output_coords = uvec2(x as u32, y as u32);
+                let color = if args.debug_view.mode != DebugView::SHADED {
+                    debug_pixel(
+                        output_coords,
+                        screen_size,
+                        camera,
+                        &octree.nodes,
+                        &args.debug_view,
+                    )
+                } else {
+                    shade_pixel(
+                        output_coords,
+                        screen_size,
+                        camera,
+                        &octree.nodes,
+                        &octree.attributes,
+                        &model.materials,
+                        &sun,
+                        &sky,
+                        environment,
+                        &args.ambient_occlusion,
+                        &Highlight::default(),
+                        args.traversal,
+                    )
+                };
+                *pixel = Vec4::from((color, 1.0));
+            }
+        });
+
+    RenderImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+#[test]
+fn test_render_cpu() {
+    use crate::loader::builtin_model;
+    use voxel_engine_shader::glam::{Mat4, Vec3};
+
+    let model = builtin_model();
+    let view = Mat4::look_at_rh(Vec3::splat(-3.0), Vec3::ZERO, -Vec3::Y);
+    let projection = Mat4::perspective_rh(45.0_f32.to_radians(), 1.0, 0.1, 100.0);
+    let camera = CameraMatrices::new(&view, &projection);
+
+    let args = Args::default();
+    let image = render_cpu(&model, None, &args, &camera, 32, 32);
+    assert_eq!(image.pixels.len(), 32 * 32);
+
+    // The sponge covers the center and the sky the corners
+    let sky = image.pixels[0];
+    let center = image.pixels[16 * 32 + 16];
+    assert_ne!(center, sky);
+    assert!(image.pixels.iter().all(|pixel| pixel.is_finite()));
+
+    // Both traversals find the same voxels
+    let esvo = Args {
+        traversal: Traversal::Esvo,
+        ..Args::default()
+    };
+    let esvo = render_cpu(&model, None, &esvo, &camera, 32, 32);
+    let different = (image.pixels.iter().zip(&esvo.pixels))
+        .filter(|(stack, esvo)| (**stack - **esvo).abs().max_element() > 1e-3)
+        .count();
+    assert!(different < 8, "{different}");
+
+    let normals = Args {
+        debug_view: DebugView {
+            mode: DebugView::NORMALS,
+        },
+        ..Args::default()
+    };
+    let normals = render_cpu(&model, None, &normals, &camera, 32, 32);
+    assert_eq!(normals.pixels[0], Vec4::new(0.0, 0.0, 0.0, 1.0));
+    assert_ne!(normals.pixels[16 * 32 + 16], Vec4::new(0.0, 0.0, 0.0, 1.0));
+}
@@ -0,0 +1,134 @@
+use crate::camera::Camera;
+use std::time::Duration;
+use voxel_engine_shader::glam::Vec3;
+use winit::event::{ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode};
+
+/// Rotation of the view per pixel of mouse movement, in radians
+pub const LOOK_SENSITIVITY: f32 = 0.003;
+
+/// Speed multiplier while shift is held
+const SPRINT_FACTOR: f32 = 4.0;
+/// Speed change per line of scrolling
+const SPEED_STEP: f32 = 1.25;
+const MIN_SPEED: f32 = 0.01;
+const MAX_SPEED: f32 = 10.0;
+
+/// First person controls moving the camera with WASD and QE
+pub struct FlyController {
+    pub enabled: bool,
+    /// Movement speed in world units per second, the octree is two units wide
+    pub speed: f32,
+    /// Held keys of the directions in camera space: right, left, up, down, forward and back
+    held: [bool; 6],
+    sprint: bool,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlyController {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            speed: 0.5,
+            held: [false; 6],
+            sprint: false,
+        }
+    }
+
+    pub fn process_keyboard(&mut self, input: &KeyboardInput) {
+        let pressed = input.state == ElementState::Pressed;
+
+        let direction = match input.virtual_keycode {
+            Some(VirtualKeyCode::D) => 0,
+            Some(VirtualKeyCode::A) => 1,
+            Some(VirtualKeyCode::E) => 2,
+            Some(VirtualKeyCode::Q) => 3,
+            Some(VirtualKeyCode::W) => 4,
+            Some(VirtualKeyCode::S) => 5,
+            Some(VirtualKeyCode::LShift | VirtualKeyCode::RShift) => {
+                self.sprint = pressed;
+                return;
+            }
+            _ => return,
+        };
+        self.held[direction] = pressed;
+    }
+
+    /// Scrolling changes the speed
+    pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => *lines,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+        };
+
+        self.speed = (self.speed * SPEED_STEP.powf(lines)).clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// Movement in camera space during a frame, diagonal movement is not faster
+    pub fn movement(&self, frame_time: Duration) -> Vec3 {
+        let axis = |positive: usize, negative: usize| {
+            self.held[positive] as i32 as f32 - self.held[negative] as i32 as f32
+        };
+        let direction = Vec3::new(axis(0, 1), axis(2, 3), axis(4, 5)).normalize_or_zero();
+
+        let speed = if self.sprint {
+            self.speed * SPRINT_FACTOR
+        } else {
+            self.speed
+        };
+
+        direction * speed * frame_time.as_secs_f32()
+    }
+
+    /// Move the camera by the held keys, returns if it moved
+    pub fn update(&self, camera: &mut Camera, frame_time: Duration) -> bool {
+        let movement = self.movement(frame_time);
+        if movement == Vec3::ZERO {
+            return false;
+        }
+
+        camera.fly_move(movement);
+        true
+    }
+}
+
+#[test]
+fn test_fly_controller() {
+    let key = |key: VirtualKeyCode, state: ElementState| {
+        #[allow(deprecated)]
+        KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(key),
+            modifiers: Default::default(),
+        }
+    };
+    let second = Duration::from_secs(1);
+
+    let mut fly = FlyController::new();
+    assert_eq!(fly.movement(second), Vec3::ZERO);
+
+    fly.process_keyboard(&key(VirtualKeyCode::W, ElementState::Pressed));
+    assert_eq!(fly.movement(second), Vec3::new(0.0, 0.0, 0.5));
+    assert_eq!(fly.movement(second / 2), Vec3::new(0.0, 0.0, 0.25));
+
+    fly.process_keyboard(&key(VirtualKeyCode::LShift, ElementState::Pressed));
+    fly.process_keyboard(&key(VirtualKeyCode::Q, ElementState::Pressed));
+    let movement = fly.movement(second);
+    assert!((movement.length() - 2.0).abs() < 1e-6);
+    assert!(movement.y < 0.0 && movement.z > 0.0);
+
+    fly.process_keyboard(&key(VirtualKeyCode::LShift, ElementState::Released));
+    fly.process_keyboard(&key(VirtualKeyCode::Q, ElementState::Released));
+    fly.process_keyboard(&key(VirtualKeyCode::S, ElementState::Pressed));
+    assert_eq!(fly.movement(second), Vec3::ZERO);
+
+    fly.process_scroll(&MouseScrollDelta::LineDelta(0.0, 1.0));
+    assert!((fly.speed - 0.625).abs() < 1e-6);
+    fly.process_scroll(&MouseScrollDelta::LineDelta(0.0, -100.0));
+    assert_eq!(fly.speed, MIN_SPEED);
+}
@@ -0,0 +1,160 @@
+//! Golden image tests, rendering the bundled vox models with the CPU renderer and comparing
+//! them against the reference images in `golden/`. Run the tests with `UPDATE_GOLDEN=1` to
+//! replace the references after an intended change of the output.
+
+use crate::args::{Args, Traversal};
+use crate::cpu_renderer::render_cpu;
+use crate::loader::{builtin_model, load_model, Model};
+use crate::png_image::{encode_srgb8, write_png_rgba8};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use voxel_engine_shader::glam::{Mat4, Vec3};
+use voxel_engine_shader::CameraMatrices;
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 96;
+
+/// Largest difference of an 8 bit channel of pixels considered equal
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels allowed to differ from the reference
+const PIXEL_TOLERANCE: f32 = 0.005;
+
+/// Camera position of the app, looking at the center of the octree
+const DEFAULT_EYE: Vec3 = Vec3::new(-3.0, -3.0, -3.0);
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+fn load_vox(name: &str) -> Model {
+    let vox_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../voxel-engine-parser/src/vox");
+    load_model(&vox_dir.join(name), &Args::default()).unwrap()
+}
+
+fn read_png_rgba8(path: &Path) -> Result<((u32, u32), Vec<u8>), String> {
+    let error = |err: &dyn std::fmt::Display| format!("can not read {}: {err}", path.display());
+
+    let file = File::open(path).map_err(|err| error(&err))?;
+    let mut reader = png::Decoder::new(file)
+        .read_info()
+        .map_err(|err| error(&err))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|err| error(&err))?;
+
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(error(&"not an 8 bit rgba image"));
+    }
+
+    Ok(((info.width, info.height), data))
+}
+
+/// Count the pixels differing beyond the tolerance, the diff image shows them red on top of a
+/// dimmed copy of the reference
+fn compare_rgba8(expected: &[u8], actual: &[u8]) -> (usize, Vec<u8>) {
+    let mut different = 0;
+    let diff = expected
+        .chunks_exact(4)
+        .zip(actual.chunks_exact(4))
+        .flat_map(|(expected, actual)| {
+            let is_different = (expected.iter().zip(actual))
+                .any(|(expected, actual)| expected.abs_diff(*actual) > CHANNEL_TOLERANCE);
+
+            if is_different {
+                different += 1;
+                [255, 0, 0, 255]
+            } else {
+                let gray = expected[..3]
+                    .iter()
+                    .map(|channel| *channel as u32)
+                    .sum::<u32>()
+                    / 9;
+                [gray as u8, gray as u8, gray as u8, 255]
+            }
+        })
+        .collect();
+
+    (different, diff)
+}
+
+/// Render a model with both traversals and compare it with the reference image `name`
+fn assert_golden(name: &str, model: &Model, eye: Vec3) {
+    let view = Mat4::look_at_rh(eye, Vec3::ZERO, -Vec3::Y);
+    let aspect = WIDTH as f32 / HEIGHT as f32;
+    let projection = Mat4::perspective_rh(45.0_f32.to_radians(), aspect, 0.1, 100.0);
+    let camera = CameraMatrices::new(&view, &projection);
+
+    let reference_path = golden_dir().join(format!("{name}.png"));
+
+    for traversal in [Traversal::Stack, Traversal::Esvo] {
+        let args = Args {
+            traversal,
+            ..Args::default()
+        };
+        let actual = encode_srgb8(&render_cpu(model, None, &args, &camera, WIDTH, HEIGHT));
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() && traversal == Traversal::Stack {
+            write_png_rgba8(&reference_path, WIDTH, HEIGHT, &actual).unwrap();
+        }
+
+        let (size, expected) = read_png_rgba8(&reference_path)
+            .unwrap_or_else(|err| panic!("{err}, run with UPDATE_GOLDEN=1 to create it"));
+        assert_eq!(size, (WIDTH, HEIGHT), "{name}");
+
+        let (different, diff) = compare_rgba8(&expected, &actual);
+        if different as f32 > PIXEL_TOLERANCE * (WIDTH * HEIGHT) as f32 {
+            let output_dir = std::env::temp_dir().join("voxel-engine-golden");
+            std::fs::create_dir_all(&output_dir).unwrap();
+            let actual_path = output_dir.join(format!("{name}-{traversal:?}.png"));
+            let diff_path = output_dir.join(format!("{name}-{traversal:?}-diff.png"));
+            write_png_rgba8(&actual_path, WIDTH, HEIGHT, &actual).unwrap();
+            write_png_rgba8(&diff_path, WIDTH, HEIGHT, &diff).unwrap();
+
+            panic!(
+                "{name}: {different} pixels of the {traversal:?} traversal differ from {}, \
+                 see {} and {}",
+                reference_path.display(),
+                actual_path.display(),
+                diff_path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_compare_rgba8() {
+    let expected = [90, 90, 90, 255, 0, 0, 0, 255];
+    let (different, diff) = compare_rgba8(&expected, &[98, 82, 90, 255, 0, 9, 0, 255]);
+    assert_eq!(different, 1);
+    assert_eq!(diff, [30, 30, 30, 255, 255, 0, 0, 255]);
+}
+
+#[test]
+fn test_golden_builtin() {
+    assert_golden("builtin", &builtin_model(), DEFAULT_EYE);
+}
+
+#[test]
+fn test_golden_menger() {
+    let model = load_vox("menger.vox");
+    assert_golden("menger", &model, DEFAULT_EYE);
+    assert_golden("menger-front", &model, Vec3::new(0.3, -0.4, -2.6));
+}
+
+#[test]
+fn test_golden_teapot() {
+    let model = load_vox("teapot.vox");
+    assert_golden("teapot", &model, DEFAULT_EYE);
+    assert_golden("teapot-side", &model, Vec3::new(3.2, -1.0, 0.5));
+}
+
+#[test]
+fn test_golden_chr_knight() {
+    assert_golden("chr_knight", &load_vox("chr_knight.vox"), DEFAULT_EYE);
+}
+
+#[test]
+fn test_golden_monu() {
+    for name in ["monu0", "monu1", "monu3", "monu9", "monu16"] {
+        assert_golden(name, &load_vox(&format!("{name}.vox")), DEFAULT_EYE);
+    }
+}
@@ -24,11 +24,18 @@ pub struct GpuModel {
     pub queue: Arc<Queue>,
 }
 
-pub fn find_gpu_model(instance: &Arc<Instance>, surface: &Arc<Surface>) -> GpuModel {
+/// Find the best device, it only needs to present to the surface if one is given
+pub fn find_gpu_model(instance: &Arc<Instance>, surface: Option<&Arc<Surface>>) -> GpuModel {
+    // Headless rendering does not need a swapchain
+    let enabled_extensions = match surface {
+        Some(_) => DEVICE_EXTENSIONS,
+        None => DeviceExtensions::empty(),
+    };
+
     let (physical, queue_family_index) = instance
         .enumerate_physical_devices()
         .expect("Failed to enumerate physical devices")
-        .filter(|p| physical_device_supported(p))
+        .filter(|p| physical_device_supported(p, &enabled_extensions))
         .filter_map(|p| find_queue_family(p, surface))
         .min_by_key(|(p, _)| rate_physical_device(p))
         .expect("No suitable physical devices available");
@@ -40,7 +47,7 @@ pub fn find_gpu_model(instance: &Arc<Instance>, surface: &Arc<Surface>) -> GpuMo
                 queue_family_index,
                 ..Default::default()
             }],
-            enabled_extensions: DEVICE_EXTENSIONS,
+            enabled_extensions,
             enabled_features: DEVICE_FEATURES,
             ..Default::default()
         },
@@ -55,8 +62,11 @@ pub fn find_gpu_model(instance: &Arc<Instance>, surface: &Arc<Surface>) -> GpuMo
     }
 }
 
-fn physical_device_supported(physical: &Arc<PhysicalDevice>) -> bool {
-    let extensions_supported = physical.supported_extensions().contains(&DEVICE_EXTENSIONS);
+fn physical_device_supported(
+    physical: &Arc<PhysicalDevice>,
+    enabled_extensions: &DeviceExtensions,
+) -> bool {
+    let extensions_supported = physical.supported_extensions().contains(enabled_extensions);
     let features_supported = physical.supported_features().contains(&DEVICE_FEATURES);
 
     extensions_supported && features_supported
@@ -64,7 +74,7 @@ fn physical_device_supported(physical: &Arc<PhysicalDevice>) -> bool {
 
 fn find_queue_family(
     physical: Arc<PhysicalDevice>,
-    surface: &Arc<Surface>,
+    surface: Option<&Arc<Surface>>,
 ) -> Option<(Arc<PhysicalDevice>, u32)> {
     physical
         .queue_family_properties()
@@ -73,9 +83,11 @@ fn find_queue_family(
         .position(|(queue_family_index, queue_family)| {
             let flags = QueueFlags::GRAPHICS & QueueFlags::COMPUTE;
             queue_family.queue_flags.contains(flags)
-                && physical
-                    .surface_support(queue_family_index as u32, &surface)
-                    .unwrap_or(false)
+                && surface.map_or(true, |surface| {
+                    physical
+                        .surface_support(queue_family_index as u32, surface)
+                        .unwrap_or(false)
+                })
         })
         .map(|queue_family_index| (physical, queue_family_index as u32))
 }
@@ -0,0 +1,201 @@
+use std::path::Path;
+use voxel_engine_shader::glam::Vec4;
+use voxel_engine_shader::Sky;
+
+/// Floating point image decoded from a Radiance `.hdr` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    /// Linear RGB pixels row by row from the top, alpha is one
+    pub pixels: Vec<Vec4>,
+}
+
+impl HdrImage {
+    /// Sky using the image as equirectangular environment map
+    pub fn sky(&self) -> Sky {
+        Sky::environment(self.width, self.height)
+    }
+}
+
+/// Convert a shared exponent pixel to linear RGB
+fn rgbe_to_rgb([r, g, b, e]: [u8; 4]) -> Vec4 {
+    if e == 0 {
+        return Vec4::new(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let scale = 2f32.powi(e as i32 - (128 + 8));
+    Vec4::new(
+        (r as f32 + 0.5) * scale,
+        (g as f32 + 0.5) * scale,
+        (b as f32 + 0.5) * scale,
+        1.0,
+    )
+}
+
+/// Split the header lines and the resolution line from the pixel data
+fn parse_header(input: &[u8]) -> Result<((u32, u32), &[u8]), String> {
+    let mut rest = input;
+    let mut next_line = || {
+        let end = rest.iter().position(|byte| *byte == b'\n')?;
+        let line = String::from_utf8_lossy(&rest[..end]).into_owned();
+        rest = &rest[end + 1..];
+        Some(line)
+    };
+
+    let magic = next_line().ok_or("missing header")?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err("not a radiance hdr file".to_string());
+    }
+
+    loop {
+        let line = next_line().ok_or("unterminated header")?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported pixel format '{format}'"));
+            }
+        }
+    }
+
+    // Only the standard orientation of rows from top to bottom is supported
+    let resolution = next_line().ok_or("missing resolution")?;
+    let size = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => width.parse().ok().zip(height.parse().ok()),
+        _ => None,
+    };
+    let size = size.ok_or_else(|| format!("unsupported resolution '{resolution}'"))?;
+    if size.0 == 0 || size.1 == 0 {
+        return Err(format!("empty image '{resolution}'"));
+    }
+
+    Ok((size, rest))
+}
+
+/// Decode a run length encoded scanline, whose channels are stored one after another
+fn decode_rle_scanline(input: &[u8], width: usize) -> Option<(Vec<[u8; 4]>, &[u8])> {
+    let mut scanline = vec![[0u8; 4]; width];
+    let mut rest = input;
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, tail) = rest.split_first()?;
+            if count > 128 {
+                // A run of a single value
+                let count = count as usize - 128;
+                let (&value, tail) = tail.split_first()?;
+                for pixel in scanline.get_mut(x..x + count)? {
+                    pixel[channel] = value;
+                }
+                x += count;
+                rest = tail;
+            } else {
+                let count = count as usize;
+                if count == 0 || tail.len() < count {
+                    return None;
+                }
+                for (pixel, value) in scanline.get_mut(x..x + count)?.iter_mut().zip(tail) {
+                    pixel[channel] = *value;
+                }
+                x += count;
+                rest = &tail[count..];
+            }
+        }
+    }
+
+    Some((scanline, rest))
+}
+
+pub fn parse_hdr(input: &[u8]) -> Result<HdrImage, String> {
+    let ((width, height), mut rest) = parse_header(input)?;
+    let row_width = width as usize;
+
+    let mut pixels = Vec::with_capacity(row_width * height as usize);
+    for _ in 0..height {
+        // Run length encoded scanlines start with 2, 2 and the width
+        let is_rle = (8..0x8000).contains(&row_width)
+            && rest.len() >= 4
+            && rest[..2] == [2, 2]
+            && (rest[2] as usize) << 8 | rest[3] as usize == row_width;
+
+        let scanline = if is_rle {
+            let (scanline, tail) =
+                decode_rle_scanline(&rest[4..], row_width).ok_or("corrupted scanline")?;
+            rest = tail;
+            scanline
+        } else {
+            if rest.len() < row_width * 4 {
+                return Err("unexpected end of pixel data".to_string());
+            }
+            let (row, tail) = rest.split_at(row_width * 4);
+            rest = tail;
+            row.chunks_exact(4)
+                .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+                .collect()
+        };
+
+        pixels.extend(scanline.into_iter().map(rgbe_to_rgb));
+    }
+
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+pub fn load_hdr(path: &Path) -> Result<HdrImage, String> {
+    let path_name = path.display();
+
+    let input = std::fs::read(path).map_err(|err| format!("can not read {path_name}: {err}"))?;
+    parse_hdr(&input).map_err(|err| format!("can not parse {path_name}: {err}"))
+}
+
+#[test]
+fn test_parse_hdr() {
+    let header = |width: u32, height: u32| {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n-Y {height} +X {width}\n")
+            .into_bytes()
+    };
+
+    // Flat 2x1 image
+    let mut input = header(2, 1);
+    input.extend([128, 64, 0, 129, 0, 0, 0, 0]);
+    let image = parse_hdr(&input).unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(
+        image.pixels,
+        [
+            Vec4::new(128.5 / 128.0, 64.5 / 128.0, 0.5 / 128.0, 1.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0)
+        ]
+    );
+
+    // Run length encoded 8x2 image, each channel a run or literal values
+    let mut input = header(8, 2);
+    for row in 0..2u8 {
+        input.extend([2, 2, 0, 8]);
+        input.extend([128 + 8, 100 + row]);
+        input.extend([8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        input.extend([128 + 4, 10, 128 + 4, 20]);
+        input.extend([128 + 8, 130]);
+    }
+    let image = parse_hdr(&input).unwrap();
+    assert_eq!(image.pixels.len(), 16);
+    assert_eq!(image.pixels[3], rgbe_to_rgb([100, 3, 10, 130]));
+    assert_eq!(image.pixels[14], rgbe_to_rgb([101, 6, 20, 130]));
+
+    assert!(parse_hdr(b"P6\n").is_err());
+    assert!(parse_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n").is_err());
+    assert!(parse_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n").is_err());
+    assert!(parse_hdr(&header(2, 2)[..]).is_err());
+    assert!(parse_hdr(&header(0, 1)[..]).is_err());
+    assert!(parse_hdr(&header(1, 0)[..]).is_err());
+
+    let mut corrupted = header(8, 1);
+    corrupted.extend([2, 2, 0, 8, 128 + 9, 0]);
+    assert!(parse_hdr(&corrupted).is_err());
+}
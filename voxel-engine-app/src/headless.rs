@@ -0,0 +1,83 @@
+use crate::allocators::Allocators;
+use crate::args::Args;
+use crate::command::record_offscreen_command_buffer;
+use crate::compute::Compute;
+use crate::context::create_headless_gpu;
+use crate::hdr::HdrImage;
+use crate::loader::Model;
+use crate::render_image::RenderImage;
+use voxel_engine_shader::glam::Vec4;
+use voxel_engine_shader::CameraMatrices;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryUsage};
+use vulkano::sync;
+use vulkano::sync::GpuFuture;
+use winit::dpi::PhysicalSize;
+
+/// Render a single frame with Vulkan into an offscreen image, without a window or surface
+pub fn render_headless(
+    model: &Model,
+    environment: Option<&HdrImage>,
+    args: &Args,
+    camera: &CameraMatrices,
+) -> RenderImage {
+    let (width, height) = args.size;
+
+    let gpu = create_headless_gpu();
+    let allocators = Allocators::new(&gpu.device);
+
+    let compute = Compute::new(
+        &gpu.device,
+        &gpu.queue,
+        PhysicalSize::new(width, height),
+        model,
+        environment,
+        args,
+        &allocators,
+    );
+
+    {
+        let mut writer = compute.camera_buffer.write().unwrap();
+        *writer = *camera;
+    }
+
+    let output_buffer = Buffer::new_slice::<Vec4>(
+        &allocators.memory,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Download,
+            ..Default::default()
+        },
+        (width * height) as u64,
+    )
+    .unwrap();
+
+    let command_buffer = record_offscreen_command_buffer(
+        &gpu.queue,
+        &compute.pipeline,
+        &allocators.command_buffer,
+        &compute.render_image_set,
+        &compute.render_image,
+        args.debug_view,
+        &output_buffer,
+    );
+
+    sync::now(gpu.device.clone())
+        .then_execute(gpu.queue, command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    let pixels = output_buffer.read().unwrap().to_vec();
+
+    RenderImage {
+        width,
+        height,
+        pixels,
+    }
+}
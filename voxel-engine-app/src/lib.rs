@@ -0,0 +1,24 @@
+//! Renderers, camera and model loading of the voxel-engine binary, shared with the integration
+//! tests
+
+#![feature(int_roundings)]
+
+pub mod allocators;
+pub mod args;
+pub mod camera;
+pub mod command;
+pub mod compute;
+pub mod context;
+pub mod cpu_renderer;
+pub mod fly;
+#[cfg(test)]
+mod golden;
+pub mod gpu_model;
+pub mod hdr;
+pub mod headless;
+pub mod loader;
+pub mod mouse;
+pub mod pick;
+pub mod png_image;
+pub mod render_image;
+pub mod swapchain;
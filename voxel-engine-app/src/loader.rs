@@ -0,0 +1,214 @@
+use crate::args::Args;
+use std::path::Path;
+use voxel_engine_parser::{parse_vox, MaterialType, VoxFile};
+use voxel_engine_shader::glam::IVec3;
+use voxel_engine_shader::{Material, Octree, VoxelAttribute};
+
+/// Number of materials, one per palette entry
+const MATERIAL_COUNT: usize = 256;
+
+/// Voxels of the shown model with the materials of their palette entries
+#[derive(Debug)]
+pub struct Model {
+    pub octree: Octree,
+    pub materials: Vec<Material>,
+    /// Rotate positions and normals of the engine space back to the coordinates of the model
+    pub to_model_space: fn(IVec3) -> IVec3,
+}
+
+/// Menger sponge shown when no vox file is given
+pub fn builtin_model() -> Model {
+    const SIZE: i32 = 27;
+
+    // A cell is empty if at least two of its base 3 digits are 1 at any level
+    let is_solid = |mut position: IVec3| {
+        while position != IVec3::ZERO {
+            let ones = (position % 3)
+                .to_array()
+                .iter()
+                .filter(|digit| **digit == 1)
+                .count();
+            if ones >= 2 {
+                return false;
+            }
+            position /= 3;
+        }
+        true
+    };
+
+    let voxels = (0..SIZE * SIZE * SIZE)
+        .map(|i| IVec3::new(i % SIZE, (i / SIZE) % SIZE, i / (SIZE * SIZE)))
+        .filter(|position| is_solid(*position))
+        .map(|position| {
+            let color = (position * 8 + 40).to_array().map(|channel| channel as u8);
+            (position, VoxelAttribute::new(color, 0))
+        });
+
+    Model {
+        octree: Octree::from_voxel_attributes(voxels).expect("menger sponge fits into an octree"),
+        materials: vec![Material::diffuse(); MATERIAL_COUNT],
+        to_model_space: |voxel| voxel,
+    }
+}
+
+/// Vox files are z up, the engine is y up
+fn to_engine_space([x, y, z]: [i32; 3]) -> IVec3 {
+    IVec3::new(x, z, -y)
+}
+
+/// Inverse of `to_engine_space`
+pub fn to_vox_space(voxel: IVec3) -> IVec3 {
+    IVec3::new(voxel.x, -voxel.z, voxel.y)
+}
+
+fn voxel_attribute(vox_file: &VoxFile, color_index: u8) -> VoxelAttribute {
+    let [r, g, b, _] = vox_file.palette.color(color_index);
+    VoxelAttribute::new([r, g, b], color_index)
+}
+
+/// Convert a vox material to the closest material of the shader.
+/// Emission is approximated by the emit property scaled with the flux power.
+fn material(vox_material: &voxel_engine_parser::Material) -> Material {
+    match vox_material.material_type {
+        MaterialType::Metal => Material::metal(vox_material.rough.unwrap_or(0.0)),
+        MaterialType::Glass => Material::glass(vox_material.refractive_index().unwrap_or(1.5)),
+        MaterialType::Emit => Material::emissive(
+            vox_material.emit.unwrap_or(0.0) * (1.0 + vox_material.flux.unwrap_or(0.0)),
+        ),
+        _ => Material::diffuse(),
+    }
+}
+
+fn vox_materials(vox_file: &VoxFile) -> Vec<Material> {
+    (0..MATERIAL_COUNT)
+        .map(|color_index| {
+            vox_file
+                .material(color_index as u8)
+                .map_or(Material::diffuse(), material)
+        })
+        .collect()
+}
+
+fn vox_voxels(vox_file: &VoxFile, args: &Args) -> Result<Vec<(IVec3, VoxelAttribute)>, String> {
+    if args.scene {
+        return Ok(vox_file
+            .flatten()
+            .iter()
+            .map(|voxel| {
+                (
+                    to_engine_space(voxel.position),
+                    voxel_attribute(vox_file, voxel.color_index),
+                )
+            })
+            .collect());
+    }
+
+    let model = vox_file.models.get(args.model).ok_or_else(|| {
+        format!(
+            "model index {} is out of range, the file contains {} models",
+            args.model,
+            vox_file.models.len()
+        )
+    })?;
+
+    Ok(model
+        .voxels()
+        .iter()
+        .map(|voxel| {
+            (
+                to_engine_space(voxel.position().map(i32::from)),
+                voxel_attribute(vox_file, voxel.color_index()),
+            )
+        })
+        .collect())
+}
+
+/// Parse a vox file and build the octree of the selected model or scene
+pub fn load_model(path: &Path, args: &Args) -> Result<Model, String> {
+    let path_name = path.display();
+
+    let input = std::fs::read(path).map_err(|err| format!("can not read {path_name}: {err}"))?;
+    let vox_file = parse_vox(&input).map_err(|err| format!("can not parse {path_name}: {err}"))?;
+
+    let voxels = vox_voxels(&vox_file, args)?;
+    if voxels.is_empty() {
+        return Err(format!("{path_name} does not contain any visible voxels"));
+    }
+
+    Ok(Model {
+        octree: Octree::from_voxel_attributes(voxels)
+            .map_err(|err| format!("can not build octree: {err}"))?,
+        materials: vox_materials(&vox_file),
+        to_model_space: to_vox_space,
+    })
+}
+
+#[test]
+fn test_load_model() {
+    let vox_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../voxel-engine-parser/src/vox");
+
+    let model = load_model(&vox_dir.join("chr_knight.vox"), &Args::default()).unwrap();
+    assert!(model.octree.nodes.len() > 1);
+    let voxel = to_engine_space([1, 2, 3]);
+    assert_eq!((model.to_model_space)(voxel), IVec3::new(1, 2, 3));
+    assert_eq!(model.materials.len(), MATERIAL_COUNT);
+    assert!(model
+        .octree
+        .attributes
+        .iter()
+        .all(|attribute| attribute.palette_index() != 0));
+
+    let scene = Args {
+        scene: true,
+        ..Args::default()
+    };
+    assert!(load_model(&vox_dir.join("monu1.vox"), &scene).is_ok());
+
+    assert_eq!(builtin_model().octree.depth, 5);
+
+    let out_of_range = Args {
+        model: 99,
+        ..Args::default()
+    };
+    let err = load_model(&vox_dir.join("chr_knight.vox"), &out_of_range).unwrap_err();
+    assert!(err.contains("out of range"));
+
+    let err = load_model(&vox_dir.join("missing.vox"), &Args::default()).unwrap_err();
+    assert!(err.starts_with("can not read"));
+
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let err = load_model(&manifest, &Args::default()).unwrap_err();
+    assert!(err.contains("not a vox file"));
+}
+
+#[test]
+fn test_vox_materials() {
+    let properties = |entries: &[(&str, &str)]| {
+        let properties = entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        voxel_engine_parser::Material::from_properties(properties)
+    };
+
+    assert_eq!(
+        material(&properties(&[("_type", "_metal"), ("_rough", "0.2")])),
+        Material::metal(0.2)
+    );
+    assert_eq!(
+        material(&properties(&[("_type", "_glass"), ("_ior", "0.5")])),
+        Material::glass(1.5)
+    );
+    assert_eq!(
+        material(&properties(&[
+            ("_type", "_emit"),
+            ("_emit", "0.5"),
+            ("_flux", "1")
+        ])),
+        Material::emissive(1.0)
+    );
+    assert_eq!(
+        material(&properties(&[("_type", "_cloud")])),
+        Material::diffuse()
+    );
+}
@@ -1,84 +1,120 @@
-#![feature(int_roundings)]
-
-mod allocators;
-mod camera;
-mod command;
-mod compute;
-mod context;
-mod gpu_model;
-mod mouse;
-mod swapchain;
-
-use allocators::*;
-use camera::*;
-use command::*;
-use compute::*;
-use context::*;
-use mouse::*;
 use std::cell::RefCell;
+use std::time::Instant;
+use voxel_engine_cpu::allocators::*;
+use voxel_engine_cpu::args::*;
+use voxel_engine_cpu::camera::*;
+use voxel_engine_cpu::command::*;
+use voxel_engine_cpu::compute::*;
+use voxel_engine_cpu::context::*;
+use voxel_engine_cpu::cpu_renderer::*;
+use voxel_engine_cpu::fly::*;
+use voxel_engine_cpu::hdr::*;
+use voxel_engine_cpu::headless::*;
+use voxel_engine_cpu::loader::*;
+use voxel_engine_cpu::mouse::*;
+use voxel_engine_cpu::pick::*;
+use voxel_engine_cpu::png_image::*;
+use voxel_engine_cpu::swapchain::*;
 
-use swapchain::*;
-
-use voxel_engine_shader::glam::Vec3;
-use voxel_engine_shader::OctreeNode;
+use voxel_engine_shader::{DebugView, Highlight};
 use vulkano::swapchain::{
     AcquireError, SwapchainCreateInfo, SwapchainCreationError, SwapchainPresentInfo,
 };
 use vulkano::sync;
 use vulkano::sync::{FlushError, GpuFuture};
 
-use winit::event::{Event, WindowEvent};
+use winit::dpi::{LogicalSize, PhysicalPosition};
+use winit::event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{CursorGrabMode, Window, WindowBuilder};
 
 fn main() {
-    run_app();
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    if args.help {
+        println!("{USAGE}");
+        return;
+    }
+
+    let model = match &args.path {
+        Some(path) => match load_model(path, &args) {
+            Ok(model) => model,
+            Err(err) => {
+                eprintln!("error: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => builtin_model(),
+    };
+
+    let environment = match &args.environment {
+        Some(path) => match load_hdr(path) {
+            Ok(image) => Some(image),
+            Err(err) => {
+                eprintln!("error: {err}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    if args.cpu || args.headless {
+        render_frame(&model, environment.as_ref(), &args);
+        return;
+    }
+
+    run_app(model, environment, args);
+}
+
+/// Render a single frame without a window into the output file
+fn render_frame(model: &Model, environment: Option<&HdrImage>, args: &Args) {
+    let (width, height) = args.size;
+    let camera = Camera::initial(&model.octree, LogicalSize::new(width as f32, height as f32));
+
+    let image = if args.headless {
+        render_headless(model, environment, args, &camera.matrices())
+    } else {
+        render_cpu(model, environment, args, &camera.matrices(), width, height)
+    };
+
+    let path = args
+        .output
+        .as_ref()
+        .expect("frames without a window have an output file");
+    if let Err(err) = write_png(path, &image) {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
 }
 
-fn run_app() {
+fn run_app(model: Model, environment: Option<HdrImage>, args: Args) {
     let event_loop = EventLoop::new();
     let window_builder = WindowBuilder::new().with_title("voxel-engine");
 
     let ctx = Context::new(&event_loop, window_builder);
     let allocators = Allocators::new(&ctx.gpu.device);
 
-    let camera = RefCell::new(Camera::new(
-        Vec3::splat(-3.0),
-        Vec3::splat(0.0),
+    let camera = RefCell::new(Camera::initial(
+        &model.octree,
         ctx.window().inner_size().to_logical(1.0),
     ));
 
     let (mut swapchain, mut images) =
         create_swapchain(&ctx.gpu.device, &ctx.surface, ctx.window().inner_size());
 
-    let octree = vec![
-        // Root node
-        OctreeNode::new(1, false, 0b00111111, 0b00000000),
-        // First 8 sub cubes
-        OctreeNode::new(8, false, 0b11111111, 0b11111110),
-        OctreeNode::new(7, false, 0b11111111, 0b11111110),
-        OctreeNode::new(6, false, 0b11111111, 0b11111110),
-        OctreeNode::new(5, false, 0b11111111, 0b11111110),
-        OctreeNode::new(4, false, 0b11111111, 0b11111110),
-        OctreeNode::new(3, false, 0b11111111, 0b11111110),
-        OctreeNode::new(2, false, 0b11111111, 0b11111110),
-        OctreeNode::new(1, false, 0b11111111, 0b11111110),
-        // 2nd level subcube
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-        OctreeNode::new(0, false, 0b11111111, 0b11111111),
-    ];
-
     let mut compute = Compute::new(
         &ctx.gpu.device,
         &ctx.gpu.queue,
         ctx.window().inner_size(),
-        octree.clone(),
+        &model,
+        environment.as_ref(),
+        &args,
         &allocators,
     );
 
@@ -87,6 +123,8 @@ fn run_app() {
         *writer = camera.borrow().matrices();
     }
 
+    let mut debug_view = args.debug_view;
+
     let mut command_buffers = record_command_buffers(
         &ctx.gpu.device,
         &ctx.gpu.queue,
@@ -95,22 +133,45 @@ fn run_app() {
         &allocators.command_buffer,
         &compute.render_image_set,
         &compute.render_image,
+        debug_view,
     );
 
     let mut window_resized = false;
     let mut recreate_swapchain = false;
 
     let mut mouse_handler = MouseHandler::new();
+    let mut fly = FlyController::new();
+    let mut last_frame = Instant::now();
+    let mut cursor_position = None;
+    let mut hovered = None;
+
+    // Frames accumulated by the path tracer since the camera last changed
+    let mut frame = 0;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent { event, .. } => {
-            mouse_handler.process_event(&event, |drag_delta| {
-                camera
-                    .borrow_mut()
-                    .arcball_rotate(drag_delta, ctx.window().inner_size().to_logical(1.0));
+            mouse_handler.process_event(&event, |action| {
+                if fly.enabled {
+                    return;
+                }
+
+                let screen_size = ctx.window().inner_size().to_logical(1.0);
+                let mut camera = camera.borrow_mut();
+                match action {
+                    MouseAction::Orbit(delta) => camera.arcball_rotate(delta, screen_size),
+                    MouseAction::Pan(delta) => camera.pan(delta, screen_size),
+                    MouseAction::Dolly(factor) => camera.dolly(factor),
+                    MouseAction::Focus(position) => {
+                        match pick(&camera, position, screen_size, &model, args.traversal) {
+                            Some(hovered) => camera.focus(hovered.hit.voxel_center()),
+                            None => return,
+                        }
+                    }
+                }
 
                 let mut writer = compute.camera_buffer.write().unwrap();
-                *writer = camera.borrow().matrices();
+                *writer = camera.matrices();
+                frame = 0;
             });
 
             match event {
@@ -120,10 +181,96 @@ fn run_app() {
                 WindowEvent::Resized(_) => {
                     window_resized = true;
                 }
+                WindowEvent::CursorMoved { position, .. } => {
+                    cursor_position = Some(position.cast());
+                }
+                WindowEvent::CursorLeft { .. } => {
+                    cursor_position = None;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::V),
+                            ..
+                        },
+                    ..
+                } if args.supports_debug_views() => {
+                    debug_view.mode = (debug_view.mode + 1) % DebugView::COUNT;
+                    println!("debug view: {}", DEBUG_VIEWS[debug_view.mode as usize]);
+
+                    command_buffers = record_command_buffers(
+                        &ctx.gpu.device,
+                        &ctx.gpu.queue,
+                        &compute.pipeline,
+                        &images,
+                        &allocators.command_buffer,
+                        &compute.render_image_set,
+                        &compute.render_image,
+                        debug_view,
+                    );
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F),
+                            ..
+                        },
+                    ..
+                } => {
+                    fly.enabled = !fly.enabled;
+                    grab_cursor(ctx.window(), fly.enabled);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::H),
+                            ..
+                        },
+                    ..
+                } => {
+                    if let Some((min, max)) = model.octree.bounds() {
+                        camera.borrow_mut().frame(min, max);
+
+                        let mut writer = compute.camera_buffer.write().unwrap();
+                        *writer = camera.borrow().matrices();
+                        frame = 0;
+                    }
+                }
+                WindowEvent::KeyboardInput { input, .. } => {
+                    fly.process_keyboard(&input);
+                }
+                WindowEvent::MouseWheel { delta, .. } if fly.enabled => {
+                    fly.process_scroll(&delta);
+                }
                 _ => {}
             }
         }
+        Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } if fly.enabled => {
+            let delta = PhysicalPosition::new(delta.0 as f32, delta.1 as f32);
+            camera.borrow_mut().fly_look(delta, LOOK_SENSITIVITY);
+
+            let mut writer = compute.camera_buffer.write().unwrap();
+            *writer = camera.borrow().matrices();
+            frame = 0;
+        }
         Event::MainEventsCleared => {
+            // Fly movement is scaled by the time since the last frame
+            let now = Instant::now();
+            let frame_time = now - last_frame;
+            last_frame = now;
+
+            if fly.enabled && fly.update(&mut camera.borrow_mut(), frame_time) {
+                let mut writer = compute.camera_buffer.write().unwrap();
+                *writer = camera.borrow().matrices();
+                frame = 0;
+            }
+
             if window_resized || recreate_swapchain {
                 recreate_swapchain = false;
 
@@ -133,7 +280,7 @@ fn run_app() {
                 }) {
                     Ok(r) => r,
                     Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
-                    Err(e) => panic!("Failed to recreate swapchain: {}", e),
+                    Err(e) => panic!("Failed to recreate swapchain: {e}"),
                 };
 
                 if window_resized {
@@ -143,7 +290,9 @@ fn run_app() {
                         &ctx.gpu.device,
                         &ctx.gpu.queue,
                         ctx.window().inner_size(),
-                        octree.clone(),
+                        &model,
+                        environment.as_ref(),
+                        &args,
                         &allocators,
                     );
 
@@ -155,6 +304,7 @@ fn run_app() {
                         let mut writer = compute.camera_buffer.write().unwrap();
                         *writer = camera.borrow().matrices();
                     }
+                    frame = 0;
                 }
 
                 command_buffers = record_command_buffers(
@@ -165,6 +315,7 @@ fn run_app() {
                     &allocators.command_buffer,
                     &compute.render_image_set,
                     &compute.render_image,
+                    debug_view,
                 );
             }
 
@@ -175,13 +326,35 @@ fn run_app() {
                         recreate_swapchain = true;
                         return;
                     }
-                    Err(e) => panic!("Failed to acquire next image: {:?}", e),
+                    Err(e) => panic!("Failed to acquire next image: {e:?}"),
                 };
 
             if suboptimal {
                 recreate_swapchain = true;
             }
 
+            // The voxel under the cursor changes with the cursor and the camera
+            let screen_size = ctx.window().inner_size().to_logical(1.0);
+            let hovered_now = match cursor_position {
+                Some(position) if !fly.enabled => pick(
+                    &camera.borrow(),
+                    position,
+                    screen_size,
+                    &model,
+                    args.traversal,
+                ),
+                _ => None,
+            };
+            if hovered_now != hovered {
+                hovered = hovered_now;
+                ctx.window().set_title(&window_title(hovered.as_ref()));
+            }
+            *compute.highlight_buffer.write().unwrap() =
+                hovered.map_or(Highlight::default(), |hovered| hovered.highlight());
+
+            compute.path_tracing_buffer.write().unwrap().frame = frame;
+            frame += 1;
+
             let execution = sync::now(ctx.gpu.device.clone())
                 .join(acquire_future)
                 .then_execute(
@@ -203,10 +376,38 @@ fn run_app() {
                     recreate_swapchain = true;
                 }
                 Err(e) => {
-                    println!("Failed to flush future: {:?}", e);
+                    println!("Failed to flush future: {e:?}");
                 }
             }
         }
         _ => {}
     });
 }
+
+/// Window title describing the voxel under the cursor
+fn window_title(hovered: Option<&VoxelHit>) -> String {
+    match hovered {
+        Some(hovered) => format!(
+            "voxel-engine - voxel {} face {} depth {:.2}",
+            hovered.voxel, hovered.face, hovered.depth
+        ),
+        None => "voxel-engine".to_string(),
+    }
+}
+
+/// Hide the cursor and keep it in the window for the mouse-look of the fly camera
+fn grab_cursor(window: &Window, grab: bool) {
+    let result = if grab {
+        // Not every platform can lock the cursor in place
+        window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined))
+    } else {
+        window.set_cursor_grab(CursorGrabMode::None)
+    };
+
+    if let Err(err) = result {
+        println!("Failed to grab the cursor: {err}");
+    }
+    window.set_cursor_visible(!grab);
+}
@@ -1,50 +1,185 @@
+use std::time::{Duration, Instant};
 use winit::dpi::PhysicalPosition;
-use winit::event::{ElementState, MouseButton, WindowEvent};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+/// Longest time between the two clicks of a double-click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// Farthest the cursor may move in pixels between the two clicks of a double-click
+const DOUBLE_CLICK_DISTANCE: f64 = 4.0;
+/// Distance to the target kept per line scrolled towards it
+const DOLLY_STEP: f32 = 0.9;
+
+/// Camera control requested with the mouse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseAction {
+    /// Left drag by a cursor delta, rotates around the target
+    Orbit(PhysicalPosition<f32>),
+    /// Middle drag by a cursor delta, moves the target along the screen
+    Pan(PhysicalPosition<f32>),
+    /// Scroll wheel, the factor scales the distance to the target
+    Dolly(f32),
+    /// Left double-click at a cursor position
+    Focus(PhysicalPosition<f32>),
+}
 
 pub struct MouseHandler {
     is_dragging: bool,
+    is_panning: bool,
     last_position: PhysicalPosition<f64>,
+    /// Time and position of the last left click, to detect double-clicks
+    last_click: Option<(Instant, PhysicalPosition<f64>)>,
+}
+
+impl Default for MouseHandler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MouseHandler {
     pub fn new() -> Self {
         Self {
             is_dragging: false,
+            is_panning: false,
             last_position: PhysicalPosition::default(),
+            last_click: None,
         }
     }
 
-    pub fn process_event(
+    pub fn process_event(&mut self, event: &WindowEvent, mut handler: impl FnMut(MouseAction)) {
+        let action = match event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.process_button(*state, *button, Instant::now())
+            }
+            WindowEvent::CursorMoved { position, .. } => self.process_cursor(*position),
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => *lines,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+                Some(MouseAction::Dolly(DOLLY_STEP.powf(lines)))
+            }
+            _ => None,
+        };
+
+        if let Some(action) = action {
+            handler(action);
+        }
+    }
+
+    fn process_button(
         &mut self,
-        event: &WindowEvent,
-        drag_handler: impl Fn(PhysicalPosition<f32>),
-    ) {
-        match event {
-            WindowEvent::MouseInput {
-                ref state,
-                button: MouseButton::Left,
-                ..
-            } => match state {
-                ElementState::Pressed => {
-                    self.is_dragging = true;
-                }
-                ElementState::Released => {
-                    self.is_dragging = false;
-                }
-            },
-            WindowEvent::CursorMoved { ref position, .. } => {
-                if self.is_dragging {
-                    let delta = PhysicalPosition::from((
-                        position.x - self.last_position.x,
-                        position.y - self.last_position.y,
-                    ));
-
-                    drag_handler(delta);
+        state: ElementState,
+        button: MouseButton,
+        now: Instant,
+    ) -> Option<MouseAction> {
+        match (button, state) {
+            (MouseButton::Left, ElementState::Pressed) => {
+                self.is_dragging = true;
+
+                let position = self.last_position;
+                let is_double_click = self.last_click.map_or(false, |(time, click_position)| {
+                    let distance =
+                        (position.x - click_position.x).hypot(position.y - click_position.y);
+                    now.duration_since(time) <= DOUBLE_CLICK_TIME
+                        && distance <= DOUBLE_CLICK_DISTANCE
+                });
+
+                if is_double_click {
+                    // A third click starts a new double-click
+                    self.last_click = None;
+                    return Some(MouseAction::Focus(position.cast()));
                 }
 
-                self.last_position = *position;
+                self.last_click = Some((now, position));
+            }
+            (MouseButton::Left, ElementState::Released) => {
+                self.is_dragging = false;
+            }
+            (MouseButton::Middle, state) => {
+                self.is_panning = state == ElementState::Pressed;
             }
             _ => {}
         }
+
+        None
+    }
+
+    fn process_cursor(&mut self, position: PhysicalPosition<f64>) -> Option<MouseAction> {
+        let delta = PhysicalPosition::from((
+            position.x - self.last_position.x,
+            position.y - self.last_position.y,
+        ));
+        self.last_position = position;
+
+        if self.is_dragging {
+            Some(MouseAction::Orbit(delta))
+        } else if self.is_panning {
+            Some(MouseAction::Pan(delta))
+        } else {
+            None
+        }
     }
 }
+
+#[test]
+fn test_mouse_handler() {
+    let mut mouse = MouseHandler::new();
+    let start = Instant::now();
+    let click = |mouse: &mut MouseHandler, time: Duration| {
+        let action = mouse.process_button(ElementState::Pressed, MouseButton::Left, start + time);
+        mouse.process_button(ElementState::Released, MouseButton::Left, start + time);
+        action
+    };
+
+    // Moving without a pressed button does nothing
+    assert_eq!(
+        mouse.process_cursor(PhysicalPosition::new(10.0, 20.0)),
+        None
+    );
+
+    // Left drags orbit and middle drags pan
+    mouse.process_button(ElementState::Pressed, MouseButton::Left, start);
+    assert_eq!(
+        mouse.process_cursor(PhysicalPosition::new(15.0, 18.0)),
+        Some(MouseAction::Orbit(PhysicalPosition::new(5.0, -2.0)))
+    );
+    mouse.process_button(ElementState::Released, MouseButton::Left, start);
+    mouse.process_button(ElementState::Pressed, MouseButton::Middle, start);
+    assert_eq!(
+        mouse.process_cursor(PhysicalPosition::new(12.0, 18.0)),
+        Some(MouseAction::Pan(PhysicalPosition::new(-3.0, 0.0)))
+    );
+    mouse.process_button(ElementState::Released, MouseButton::Middle, start);
+    assert_eq!(
+        mouse.process_cursor(PhysicalPosition::new(12.0, 18.0)),
+        None
+    );
+
+    // Two quick clicks at the same place focus, slow or distant ones do not
+    let second = Duration::from_secs(1);
+    assert_eq!(click(&mut mouse, second), None);
+    assert_eq!(
+        click(&mut mouse, second + Duration::from_millis(200)),
+        Some(MouseAction::Focus(PhysicalPosition::new(12.0, 18.0)))
+    );
+    assert_eq!(click(&mut mouse, second * 3), None);
+    assert_eq!(click(&mut mouse, second * 4), None);
+    mouse.process_cursor(PhysicalPosition::new(40.0, 18.0));
+    assert_eq!(
+        click(&mut mouse, second * 4 + Duration::from_millis(100)),
+        None
+    );
+
+    // Scrolling towards the target shrinks the distance
+    #[allow(deprecated)]
+    let scroll = WindowEvent::MouseWheel {
+        device_id: unsafe { winit::event::DeviceId::dummy() },
+        delta: MouseScrollDelta::LineDelta(0.0, 2.0),
+        phase: winit::event::TouchPhase::Moved,
+        modifiers: Default::default(),
+    };
+    let mut dolly = None;
+    mouse.process_event(&scroll, |action| dolly = Some(action));
+    assert_eq!(dolly, Some(MouseAction::Dolly(DOLLY_STEP * DOLLY_STEP)));
+}
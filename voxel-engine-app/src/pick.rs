@@ -0,0 +1,121 @@
+use crate::camera::Camera;
+use crate::loader::Model;
+use voxel_engine_shader::glam::IVec3;
+use voxel_engine_shader::{Highlight, OctreeHit, OctreeTraversal};
+use winit::dpi::{LogicalSize, PhysicalPosition};
+
+/// Voxel under the cursor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelHit {
+    /// Position of the voxel in the coordinates of the loaded model, z up for vox files
+    pub voxel: IVec3,
+    /// Normal of the face under the cursor in model coordinates, zero if the camera is inside
+    /// the voxel
+    pub face: IVec3,
+    /// Distance from the camera to the face in world space
+    pub depth: f32,
+    /// Hit of the octree traversal
+    pub hit: OctreeHit,
+}
+
+impl VoxelHit {
+    /// Highlight of the voxel in the render
+    pub fn highlight(&self) -> Highlight {
+        Highlight::voxel(self.hit.voxel)
+    }
+}
+
+/// Find the voxel under a cursor position with the octree traversal of the renderer
+pub fn pick(
+    camera: &Camera,
+    cursor_position: PhysicalPosition<f32>,
+    screen_size: LogicalSize<f32>,
+    model: &Model,
+    traversal: impl OctreeTraversal,
+) -> Option<VoxelHit> {
+    let ray = camera.cursor_ray(cursor_position, screen_size);
+    let hit = traversal.trace(&ray, &model.octree.nodes);
+    if !hit.is_hit() {
+        return None;
+    }
+
+    Some(VoxelHit {
+        voxel: (model.to_model_space)(hit.voxel.as_ivec3() + model.octree.origin),
+        face: (model.to_model_space)(hit.normal.round().as_ivec3()),
+        depth: (hit.position - ray.origin).length(),
+        hit,
+    })
+}
+
+#[test]
+fn test_pick() {
+    use crate::args::Traversal;
+    use crate::loader::to_vox_space;
+    use voxel_engine_shader::glam::{Vec3, Vec4, Vec4Swizzles};
+    use voxel_engine_shader::Octree;
+
+    // A 4x4 wall at z = 0 seen from the front
+    let voxels = (0..16).map(|i| IVec3::new(i % 4, i / 4, 0));
+    let mut model = Model {
+        octree: Octree::from_voxels(voxels).unwrap(),
+        materials: Vec::new(),
+        to_model_space: |voxel| voxel,
+    };
+    let screen_size = LogicalSize::new(400.0, 300.0);
+    let camera = Camera::new(Vec3::new(0.0, 0.0, -3.0), Vec3::ZERO, screen_size);
+
+    // Center of the front face of a voxel and the cursor position over it
+    let face_center = |voxel: IVec3| {
+        let size = model.octree.voxel_size();
+        model.octree.voxel_to_world(voxel) + Vec3::new(size, size, 0.0) / 2.0
+    };
+    let cursor = |voxel: IVec3| {
+        let clip = camera.projection * camera.view * Vec4::from((face_center(voxel), 1.0));
+        let screen = (clip.xy() / clip.w + 1.0) / 2.0;
+        PhysicalPosition::new(screen.x * screen_size.width, screen.y * screen_size.height)
+    };
+
+    for traversal in [Traversal::Stack, Traversal::Esvo] {
+        let hit = pick(
+            &camera,
+            cursor(IVec3::new(1, 2, 0)),
+            screen_size,
+            &model,
+            traversal,
+        );
+        let hit = hit.unwrap();
+        assert_eq!(hit.voxel, IVec3::new(1, 2, 0));
+        assert_eq!(hit.face, IVec3::new(0, 0, -1));
+        let depth = (face_center(hit.voxel) - camera.position).length();
+        assert!((hit.depth - depth).abs() < 1e-4, "{}", hit.depth);
+        assert_eq!(hit.highlight(), Highlight::voxel(hit.hit.voxel));
+
+        // Up in the world is up on the screen
+        assert!(cursor(IVec3::new(1, 3, 0)).y < cursor(IVec3::new(1, 2, 0)).y);
+        let above = pick(
+            &camera,
+            cursor(IVec3::new(1, 3, 0)),
+            screen_size,
+            &model,
+            traversal,
+        );
+        assert_eq!(above.unwrap().voxel, IVec3::new(1, 3, 0));
+
+        let corner = PhysicalPosition::new(0.0, 0.0);
+        assert_eq!(pick(&camera, corner, screen_size, &model, traversal), None);
+    }
+
+    // Vox files are z up, the wall is in the xz plane of the file
+    model.to_model_space = to_vox_space;
+    let hit = pick(
+        &camera,
+        cursor(IVec3::new(1, 2, 0)),
+        screen_size,
+        &model,
+        Traversal::Stack,
+    );
+    let hit = hit.unwrap();
+    assert_eq!(hit.voxel, IVec3::new(1, 0, 2));
+    assert_eq!(hit.face, IVec3::new(0, 1, 0));
+    assert_eq!(hit.highlight(), Highlight::voxel(hit.hit.voxel));
+}
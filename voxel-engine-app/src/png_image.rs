@@ -0,0 +1,75 @@
+use crate::render_image::RenderImage;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Convert a linear color channel to 8 bit sRGB, clamping it like the blit to the swapchain
+pub fn linear_to_srgb8(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (srgb * 255.0).round() as u8
+}
+
+/// RGBA bytes of a linear image in sRGB
+pub fn encode_srgb8(image: &RenderImage) -> Vec<u8> {
+    image
+        .pixels
+        .iter()
+        .flat_map(|pixel| {
+            let [r, g, b, _] = pixel.to_array().map(linear_to_srgb8);
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+/// Write a linear image as sRGB png
+pub fn write_png(path: &Path, image: &RenderImage) -> Result<(), String> {
+    write_png_rgba8(path, image.width, image.height, &encode_srgb8(image))
+}
+
+/// Write RGBA bytes as png
+pub fn write_png_rgba8(path: &Path, width: u32, height: u32, data: &[u8]) -> Result<(), String> {
+    let path_name = path.display();
+    let error = |err: &dyn std::fmt::Display| format!("can not write {path_name}: {err}");
+
+    let file = File::create(path).map_err(|err| error(&err))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|err| error(&err))?;
+    writer.write_image_data(data).map_err(|err| error(&err))
+}
+
+#[test]
+fn test_write_png() {
+    use voxel_engine_shader::glam::Vec4;
+
+    assert_eq!(linear_to_srgb8(-1.0), 0);
+    assert_eq!(linear_to_srgb8(0.5), 188);
+    assert_eq!(linear_to_srgb8(1.0), 255);
+    assert_eq!(linear_to_srgb8(4.0), 255);
+
+    let image = RenderImage {
+        width: 2,
+        height: 1,
+        pixels: vec![Vec4::new(1.0, 0.0, 0.5, 1.0), Vec4::new(0.0, 2.0, 0.0, 1.0)],
+    };
+    let path = std::env::temp_dir().join("voxel-engine-test-write-png.png");
+    write_png(&path, &image).unwrap();
+
+    let decoder = png::Decoder::new(File::open(&path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((info.width, info.height), (2, 1));
+    assert_eq!(data, [255, 0, 188, 255, 0, 255, 0, 255]);
+    assert!(write_png(Path::new("/nonexistent/frame.png"), &image).is_err());
+}
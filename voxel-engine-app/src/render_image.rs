@@ -0,0 +1,10 @@
+use voxel_engine_shader::glam::Vec4;
+
+/// Frame rendered by the compute shader or on the host, before the conversion to sRGB
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderImage {
+    pub width: u32,
+    pub height: u32,
+    /// Linear RGB pixels row by row from the top, alpha is one
+    pub pixels: Vec<Vec4>,
+}
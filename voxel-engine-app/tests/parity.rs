@@ -0,0 +1,168 @@
+//! Parity of the SPIR-V compute shader with the host execution of the same code. Every scene is
+//! rendered by the binary with `--headless` through Vulkan and with `--cpu` on the host, then the
+//! images are compared pixel by pixel.
+//!
+//! The test needs a Vulkan driver and is ignored by default. Run it on a software driver such as
+//! lavapipe with `VK_ICD_FILENAMES=<lvp_icd.json> cargo test --test parity -- --ignored`, as
+//! the parity job of the CI workflow does.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use voxel_engine_cpu::args::Args;
+use voxel_engine_cpu::camera::Camera;
+use voxel_engine_cpu::loader::{builtin_model, load_model};
+use voxel_engine_shader::glam::UVec2;
+use winit::dpi::{LogicalSize, PhysicalPosition};
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 64;
+
+/// Largest difference of an 8 bit channel of pixels considered equal
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels allowed to differ, rays grazing voxel edges can hit different voxels
+const PIXEL_TOLERANCE: f32 = 0.01;
+/// Number of mismatching pixels printed with their rays
+const MAX_REPORTED: usize = 16;
+
+struct Scene {
+    name: &'static str,
+    /// Vox file in the bundled models, the builtin octree is shown if not set
+    model: Option<&'static str>,
+    args: &'static [&'static str],
+}
+
+const SCENES: &[Scene] = &[
+    Scene {
+        name: "builtin",
+        model: None,
+        args: &[],
+    },
+    Scene {
+        name: "builtin-esvo",
+        model: None,
+        args: &["--traversal", "esvo"],
+    },
+    Scene {
+        name: "menger-normals",
+        model: Some("menger.vox"),
+        args: &["--debug-view", "normals"],
+    },
+    Scene {
+        name: "menger-iterations",
+        model: Some("menger.vox"),
+        args: &["--debug-view", "iterations"],
+    },
+    Scene {
+        name: "teapot",
+        model: Some("teapot.vox"),
+        args: &["--ao-samples", "16"],
+    },
+    Scene {
+        name: "chr_knight",
+        model: Some("chr_knight.vox"),
+        args: &["--ao-samples", "0", "--turbidity", "6"],
+    },
+    Scene {
+        name: "monu9",
+        model: Some("monu9.vox"),
+        args: &[],
+    },
+];
+
+fn vox_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../voxel-engine-parser/src/vox")
+}
+
+/// Render a scene with the binary in the given mode and read back the RGBA bytes
+fn render(scene: &Scene, mode: &str) -> Vec<u8> {
+    let output = std::env::temp_dir().join(format!("voxel-engine-parity-{}{mode}.png", scene.name));
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_voxel-engine"));
+    command
+        .args(scene.args)
+        .arg(mode)
+        .arg("--output")
+        .arg(&output)
+        .arg("--size")
+        .arg(format!("{WIDTH}x{HEIGHT}"));
+    if let Some(model) = scene.model {
+        command.arg(vox_dir().join(model));
+    }
+
+    let status = command.status().expect("can not run voxel-engine");
+    assert!(
+        status.success(),
+        "{}: rendering with {mode} failed",
+        scene.name
+    );
+
+    let mut reader = png::Decoder::new(File::open(&output).unwrap())
+        .read_info()
+        .unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    data
+}
+
+/// Camera the binary renders a scene with
+fn scene_camera(scene: &Scene) -> Camera {
+    let model = match scene.model {
+        Some(model) => load_model(&vox_dir().join(model), &Args::default()).unwrap(),
+        None => builtin_model(),
+    };
+
+    Camera::initial(&model.octree, LogicalSize::new(WIDTH as f32, HEIGHT as f32))
+}
+
+#[test]
+#[ignore = "needs a Vulkan driver such as lavapipe"]
+fn test_gpu_cpu_parity() {
+    let mut failures = Vec::new();
+
+    for scene in SCENES {
+        let gpu = render(scene, "--headless");
+        let cpu = render(scene, "--cpu");
+
+        let mismatches = (gpu.chunks_exact(4).zip(cpu.chunks_exact(4)))
+            .enumerate()
+            .filter(|(_, (gpu, cpu))| {
+                (gpu.iter().zip(cpu.iter()))
+                    .any(|(gpu, cpu)| gpu.abs_diff(*cpu) > CHANNEL_TOLERANCE)
+            })
+            .map(|(index, (gpu, cpu))| {
+                let pixel = UVec2::new(index as u32 % WIDTH, index as u32 / WIDTH);
+                (pixel, gpu, cpu)
+            })
+            .collect::<Vec<_>>();
+
+        let camera = scene_camera(scene);
+        for (pixel, gpu, cpu) in mismatches.iter().take(MAX_REPORTED) {
+            let position = PhysicalPosition::new(pixel.x as f32, pixel.y as f32);
+            let ray = camera.cursor_ray(position, LogicalSize::new(WIDTH as f32, HEIGHT as f32));
+            println!(
+                "{}: pixel {pixel} gpu {gpu:?} cpu {cpu:?} ray origin {} direction {}",
+                scene.name, ray.origin, ray.direction
+            );
+        }
+
+        let allowed = PIXEL_TOLERANCE * (WIDTH * HEIGHT) as f32;
+        // The largest difference is printed to calibrate the tolerances
+        let max_difference = (gpu.iter().zip(&cpu))
+            .map(|(gpu, cpu)| gpu.abs_diff(*cpu))
+            .max()
+            .unwrap_or(0);
+        println!(
+            "{}: {} mismatching pixels, largest channel difference {max_difference}",
+            scene.name,
+            mismatches.len()
+        );
+        if mismatches.len() as f32 > allowed {
+            failures.push(scene.name);
+        }
+    }
+
+    assert!(failures.is_empty(), "gpu and cpu differ in {failures:?}");
+}
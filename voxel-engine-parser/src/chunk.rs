@@ -0,0 +1,132 @@
+use crate::material::{parse_material, Material};
+use crate::scene::{
+    parse_group_node, parse_layer, parse_shape_node, parse_transform_node, GroupNode, Layer,
+    ShapeNode, TransformNode,
+};
+use crate::{VoxError, Voxel};
+use nom::bytes::complete::take;
+use nom::combinator::map;
+use nom::error::ErrorKind;
+use nom::multi::count;
+use nom::number::complete::{le_u32, u8};
+use nom::sequence::tuple;
+use nom::IResult;
+
+/// Chunk with its content and children bytes
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Chunk<'a> {
+    pub id: &'a str,
+    pub content: &'a [u8],
+    pub children: &'a [u8],
+}
+
+#[derive(Debug)]
+pub(crate) enum ChunkContent {
+    Pack(u32),
+    Size([u32; 3]),
+    Xyzi(Vec<Voxel>),
+    Rgba(Vec<[u8; 4]>),
+    Material(i32, Material),
+    Transform(TransformNode),
+    Group(GroupNode),
+    Shape(ShapeNode),
+    Layer(Layer),
+}
+
+/// Split the next chunk off the input, checking that its declared sizes fit
+pub(crate) fn parse_chunk(input: &[u8]) -> Result<(&[u8], Chunk), VoxError> {
+    let header: IResult<_, _> = tuple((take(4usize), le_u32, le_u32))(input);
+    let Ok((input, (id, num_content_bytes, num_children_bytes))) = header else {
+        let id = &input[..input.len().min(4)];
+        return Err(VoxError::TruncatedChunk(String::from_utf8_lossy(id).into_owned()));
+    };
+
+    if !id.iter().all(u8::is_ascii_graphic) {
+        return Err(VoxError::InvalidChunkId(id.try_into().unwrap()));
+    }
+    let id = std::str::from_utf8(id).unwrap();
+
+    let num_content_bytes = num_content_bytes as usize;
+    let num_children_bytes = num_children_bytes as usize;
+    if num_content_bytes > input.len() || num_children_bytes > input.len() - num_content_bytes {
+        return Err(VoxError::TruncatedChunk(id.to_string()));
+    }
+
+    let (content, input) = input.split_at(num_content_bytes);
+    let (children, input) = input.split_at(num_children_bytes);
+
+    Ok((
+        input,
+        Chunk {
+            id,
+            content,
+            children,
+        },
+    ))
+}
+
+fn parse_pack_chunk_content(input: &[u8]) -> IResult<&[u8], ChunkContent> {
+    map(le_u32, ChunkContent::Pack)(input)
+}
+
+fn parse_size_chunk_content(input: &[u8]) -> IResult<&[u8], ChunkContent> {
+    map(tuple((le_u32, le_u32, le_u32)), |(x, y, z)| {
+        ChunkContent::Size([x, y, z])
+    })(input)
+}
+
+fn parse_voxel(input: &[u8]) -> IResult<&[u8], Voxel> {
+    map(tuple((u8, u8, u8, u8)), |(x, y, z, color_index)| {
+        Voxel::new(x, y, z, color_index)
+    })(input)
+}
+
+fn parse_xyzi_chunk_content(input: &[u8]) -> IResult<&[u8], ChunkContent> {
+    let (input, num_voxels) = le_u32(input)?;
+
+    map(count(parse_voxel, num_voxels as usize), ChunkContent::Xyzi)(input)
+}
+
+fn parse_rgba_chunk_content(input: &[u8]) -> IResult<&[u8], ChunkContent> {
+    let color = map(tuple((u8, u8, u8, u8)), |(r, g, b, a)| [r, g, b, a]);
+
+    map(count(color, 256), ChunkContent::Rgba)(input)
+}
+
+/// Parse the content of a chunk, unknown chunks are skipped
+pub(crate) fn parse_chunk_content(chunk: &Chunk) -> Result<Option<ChunkContent>, VoxError> {
+    let input = chunk.content;
+
+    let result = match chunk.id {
+        "PACK" => parse_pack_chunk_content(input),
+        "SIZE" => parse_size_chunk_content(input),
+        "XYZI" => {
+            let expected = le_u32::<_, ()>(input).map_or(0, |(_, count)| count as usize);
+            let actual = input.len().saturating_sub(4) / 4;
+
+            if expected != actual {
+                return Err(VoxError::VoxelCountMismatch { expected, actual });
+            }
+
+            parse_xyzi_chunk_content(input)
+        }
+        "RGBA" => parse_rgba_chunk_content(input),
+        "MATL" => map(parse_material, |(id, material)| {
+            ChunkContent::Material(id, material)
+        })(input),
+        "nTRN" => map(parse_transform_node, ChunkContent::Transform)(input),
+        "nGRP" => map(parse_group_node, ChunkContent::Group)(input),
+        "nSHP" => map(parse_shape_node, ChunkContent::Shape)(input),
+        "LAYR" => map(parse_layer, ChunkContent::Layer)(input),
+        // Unknown chunks are skipped, like the camera and render settings of newer versions
+        _ => return Ok(None),
+    };
+
+    match result {
+        Ok((_, content)) => Ok(Some(content)),
+        Err(nom::Err::Error(error) | nom::Err::Failure(error)) if error.code != ErrorKind::Eof => {
+            Err(VoxError::InvalidChunk(chunk.id.to_string()))
+        }
+        Err(_) => Err(VoxError::TruncatedChunk(chunk.id.to_string())),
+    }
+}
@@ -0,0 +1,38 @@
+use nom::combinator::map;
+use nom::multi::{length_count, length_data};
+use nom::number::complete::le_u32;
+use nom::sequence::pair;
+use nom::IResult;
+use std::collections::HashMap;
+
+/// Key value attributes attached to scene nodes and materials
+pub type Dict = HashMap<String, String>;
+
+pub(crate) fn parse_string(input: &[u8]) -> IResult<&[u8], String> {
+    map(length_data(le_u32), |bytes: &[u8]| {
+        String::from_utf8_lossy(bytes).into_owned()
+    })(input)
+}
+
+pub(crate) fn parse_dict(input: &[u8]) -> IResult<&[u8], Dict> {
+    map(
+        length_count(le_u32, pair(parse_string, parse_string)),
+        |entries| entries.into_iter().collect(),
+    )(input)
+}
+
+#[test]
+fn test_parse_dict() {
+    let input = [
+        2, 0, 0, 0, // Number of entries
+        2, 0, 0, 0, b'_', b't', 5, 0, 0, 0, b'1', b' ', b'2', b' ', b'3', // _t: "1 2 3"
+        2, 0, 0, 0, b'_', b'r', 1, 0, 0, 0, b'4', // _r: "4"
+    ];
+
+    let (rest, dict) = parse_dict(&input).unwrap();
+
+    assert!(rest.is_empty());
+    assert_eq!(dict.len(), 2);
+    assert_eq!(dict["_t"], "1 2 3");
+    assert_eq!(dict["_r"], "4");
+}
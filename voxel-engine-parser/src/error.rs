@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoxError {
+    /// The file does not start with `VOX `
+    BadMagic,
+    UnsupportedVersion(i32),
+    /// The first chunk of the file is not a `MAIN` chunk
+    MissingMainChunk,
+    /// A chunk ends before its header, content or children are complete
+    TruncatedChunk(String),
+    /// A chunk id contains bytes that are not printable ASCII characters
+    InvalidChunkId([u8; 4]),
+    /// The content of a chunk has an invalid value
+    InvalidChunk(String),
+    /// The voxel count of a `XYZI` chunk does not match the size of its content
+    VoxelCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl Display for VoxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoxError::BadMagic => write!(f, "not a vox file"),
+            VoxError::UnsupportedVersion(version) => {
+                write!(f, "unsupported vox version {version}")
+            }
+            VoxError::MissingMainChunk => write!(f, "no MAIN chunk found"),
+            VoxError::TruncatedChunk(id) => write!(f, "chunk {id} is truncated"),
+            VoxError::InvalidChunkId(id) => write!(f, "invalid chunk id {id:?}"),
+            VoxError::InvalidChunk(id) => write!(f, "chunk {id} is invalid"),
+            VoxError::VoxelCountMismatch { expected, actual } => write!(
+                f,
+                "XYZI chunk declares {expected} voxels but contains {actual}"
+            ),
+        }
+    }
+}
+
+impl Error for VoxError {}
@@ -1,105 +1,165 @@
-use nom::bytes::complete::{tag, take};
-use nom::combinator::{map, map_res};
-use nom::number::complete::{be_i32, be_u32, le_i32, le_u32};
-use nom::sequence::{pair, preceded, tuple};
-use nom::IResult;
-
-#[derive(Debug, Clone, Copy)]
-struct ChunkHeader<'a> {
-    id: &'a str,
-    num_content_bytes: i32,
-    num_children_bytes: i32,
-}
+mod chunk;
+mod dict;
+mod error;
+mod material;
+mod model;
+mod palette;
+mod scene;
+mod writer;
 
-#[derive(Default, Debug, Clone, Copy)]
-pub struct Voxel {
-    x: u32,
-    y: u32,
-    z: u32,
-    color_index: u32,
-}
+pub use dict::Dict;
+pub use error::*;
+pub use material::*;
+pub use model::*;
+pub use palette::*;
+pub use scene::*;
+pub use writer::*;
 
-#[derive(Debug)]
-pub enum ChunkContent {
-    None,
-    Size(i32, i32, i32),
-    Xyzi(Vec<Voxel>),
-}
+use chunk::{parse_chunk, parse_chunk_content, ChunkContent};
+use std::collections::HashMap;
 
-fn parse_chunk_header<'a>(input: &'a [u8]) -> IResult<&[u8], ChunkHeader<'a>> {
-    map(
-        tuple((take(4usize), le_i32, le_i32)),
-        |(id, num_content_bytes, num_children_bytes)| ChunkHeader {
-            id: std::str::from_utf8(id).unwrap(),
-            num_content_bytes,
-            num_children_bytes,
-        },
-    )(input)
-}
+const MAGIC: &[u8] = b"VOX ";
+const SUPPORTED_VERSIONS: [i32; 2] = [150, 200];
 
-fn parse_size_chunk_content(input: &[u8]) -> IResult<&[u8], ChunkContent> {
-    map(tuple((le_i32, le_i32, le_i32)), |(x, y, z)| {
-        ChunkContent::Size(x, y, z)
-    })(input)
+/// Contents of a MagicaVoxel file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VoxFile {
+    /// Models of the `SIZE` and `XYZI` chunks, in file order
+    pub models: Vec<VoxModel>,
+    /// Colors of the `RGBA` chunk, or the default palette
+    pub palette: Palette,
+    /// Materials of the `MATL` chunks by palette index
+    pub materials: HashMap<u8, Material>,
+    /// Scene graph of the `nTRN`, `nGRP` and `nSHP` chunks, if the file has one
+    pub scene: Option<Scene>,
+    /// Layers of the `LAYR` chunks, in file order
+    pub layers: Vec<Layer>,
 }
 
-fn parse_xyzi_chunk_content(input: &[u8]) -> IResult<&[u8], ChunkContent> {
-    let mut voxels = Vec::new();
+impl VoxFile {
+    fn from_chunk_contents(chunk_contents: Vec<ChunkContent>) -> Result<Self, VoxError> {
+        let mut vox_file = VoxFile::default();
+        let mut scene_nodes = Vec::new();
+        let mut model_size = None;
+
+        for content in chunk_contents {
+            match content {
+                // The model count is implied by the SIZE and XYZI chunks
+                ChunkContent::Pack(_) => {}
+                ChunkContent::Size(size) => {
+                    model_size = Some(size);
+                }
+                ChunkContent::Xyzi(voxels) => {
+                    // Every model is stored as a SIZE chunk followed by a XYZI chunk
+                    let size = model_size
+                        .take()
+                        .ok_or_else(|| VoxError::InvalidChunk("XYZI".to_string()))?;
+                    vox_file.models.push(VoxModel::new(size, voxels));
+                }
+                ChunkContent::Rgba(colors) => {
+                    vox_file.palette = Palette::from_rgba(&colors);
+                }
+                ChunkContent::Material(id, material) => {
+                    if let Ok(index) = u8::try_from(id) {
+                        vox_file.materials.insert(index, material);
+                    }
+                }
+                ChunkContent::Transform(node) => scene_nodes.push(SceneNode::Transform(node)),
+                ChunkContent::Group(node) => scene_nodes.push(SceneNode::Group(node)),
+                ChunkContent::Shape(node) => scene_nodes.push(SceneNode::Shape(node)),
+                ChunkContent::Layer(layer) => vox_file.layers.push(layer),
+            }
+        }
+
+        if !scene_nodes.is_empty() {
+            vox_file.scene = Some(Scene::new(scene_nodes));
+        }
+
+        Ok(vox_file)
+    }
 
-    let (mut input, num_voxel_bytes) = le_i32(input)?;
+    /// Get the material of a palette entry, if the file defines one
+    pub fn material(&self, color_index: u8) -> Option<&Material> {
+        self.materials.get(&color_index)
+    }
 
-    let mut voxel = Voxel::default();
-    for i in 0..(num_voxel_bytes / 4) {
-        (input, voxel) = map(
-            tuple((le_u32, le_u32, le_u32, le_u32)),
-            |(x, y, z, color_index)| Voxel {
-                x,
-                y,
-                z,
-                color_index,
-            },
-        )(input)?;
+    pub fn layer(&self, id: i32) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.id == id)
+    }
 
-        voxels.push(voxel);
+    /// Get every visible model instance of the scene.
+    ///
+    /// Files without a scene graph place each model once, centered at the origin.
+    pub fn instances(&self) -> Vec<ModelInstance> {
+        match &self.scene {
+            Some(scene) => scene
+                .instances()
+                .into_iter()
+                .filter(|instance| !self.layer(instance.layer_id).map_or(false, Layer::hidden))
+                .collect(),
+            None => (0..self.models.len())
+                .map(|model_id| ModelInstance {
+                    model_id,
+                    layer_id: 0,
+                    transform: Transform::IDENTITY,
+                })
+                .collect(),
+        }
     }
 
-    Ok((input, ChunkContent::Xyzi(voxels)))
+    /// Place the voxels of every model instance in world space
+    pub fn flatten(&self) -> Vec<WorldVoxel> {
+        let mut world_voxels = Vec::new();
+
+        for instance in self.instances() {
+            let Some(model) = self.models.get(instance.model_id) else {
+                continue;
+            };
+
+            let size = model.size().map(|extent| extent as i32);
+            world_voxels.extend(model.voxels().iter().map(|voxel| {
+                WorldVoxel {
+                    position: instance
+                        .transform
+                        .apply_to_voxel(voxel.position().map(i32::from), size),
+                    color_index: voxel.color_index(),
+                }
+            }));
+        }
+
+        world_voxels
+    }
 }
 
-pub fn parse_vox(input: &[u8]) -> IResult<&[u8], Vec<ChunkContent>> {
-    let (input, version) = preceded(tag("VOX "), le_i32)(input)?;
+pub fn parse_vox(input: &[u8]) -> Result<VoxFile, VoxError> {
+    if input.len() < 8 || &input[..4] != MAGIC {
+        return Err(VoxError::BadMagic);
+    }
 
-    if version != 150 {
-        panic!("Unsupported vox version");
+    let version = i32::from_le_bytes(input[4..8].try_into().unwrap());
+    if !SUPPORTED_VERSIONS.contains(&version) {
+        return Err(VoxError::UnsupportedVersion(version));
     }
 
     // Parse main chunk
-    let (mut input, mut chunk_header) = parse_chunk_header(input)?;
-    if chunk_header.id != "MAIN" {
-        panic!("No main chunk found");
+    let (_, main_chunk) = parse_chunk(&input[8..])?;
+    if main_chunk.id != "MAIN" {
+        return Err(VoxError::MissingMainChunk);
     }
 
     let mut chunk_contents = Vec::new();
-    let mut chunk_content = None;
-
-    while !input.is_empty() {
-        (input, chunk_header) = parse_chunk_header(input)?;
-        (input, chunk_content) = match chunk_header.id {
-            "SIZE" => map(parse_size_chunk_content, |res| Some(res))(input)?,
-            "XYZI" => map(parse_xyzi_chunk_content, |res| Some(res))(input)?,
-            _ => {
-                println!("Drop chunk {}", chunk_header.id);
-                (&input[chunk_header.num_content_bytes as usize..], None)
-            }
-        };
+    let mut children = main_chunk.children;
 
-        if let Some(content) = chunk_content {
-            println!("Load chunk {}", chunk_header.id);
+    while !children.is_empty() {
+        let (rest, chunk) = parse_chunk(children)?;
+        children = rest;
+
+        if let Some(content) = parse_chunk_content(&chunk)? {
             chunk_contents.push(content);
         }
     }
 
-    Ok((input, chunk_contents))
+    VoxFile::from_chunk_contents(chunk_contents)
 }
 
 #[test]
@@ -107,3 +167,239 @@ pub fn test_parser() {
     let model = include_bytes!("vox/menger.vox");
     parse_vox(model).unwrap();
 }
+
+#[test]
+pub fn test_models() {
+    let model = include_bytes!("vox/monu10.vox");
+    let vox_file = parse_vox(model).unwrap();
+
+    assert_eq!(vox_file.models.len(), 1);
+
+    let model = &vox_file.models[0];
+    let [size_x, size_y, size_z] = model.size();
+    assert!(!model.voxels().is_empty());
+    assert!(model.voxels().iter().all(|voxel| {
+        (voxel.x() as u32) < size_x && (voxel.y() as u32) < size_y && (voxel.z() as u32) < size_z
+    }));
+}
+
+#[test]
+pub fn test_palette() {
+    let model = include_bytes!("vox/monu1.vox");
+    let vox_file = parse_vox(model).unwrap();
+
+    assert_ne!(vox_file.palette, Palette::default());
+
+    for model in &vox_file.models {
+        assert!(!model.voxels().is_empty());
+        assert!(model
+            .voxels()
+            .iter()
+            .all(|voxel| voxel.color_index() != 0 && voxel.color(&vox_file.palette)[3] != 0));
+    }
+}
+
+#[test]
+pub fn test_default_palette_fallback() {
+    assert_eq!(VoxFile::default().palette, Palette::default());
+}
+
+#[test]
+pub fn test_scene() {
+    let model = include_bytes!("vox/chr_knight.vox");
+    let vox_file = parse_vox(model).unwrap();
+
+    let instances = vox_file.scene.as_ref().unwrap().instances();
+    assert_eq!(instances.len(), 1);
+    assert_eq!(instances[0].model_id, 0);
+
+    assert_eq!(vox_file.layers.len(), 8);
+    assert_eq!(vox_file.materials.len(), 256);
+    assert_eq!(vox_file.flatten().len(), vox_file.models[0].voxels().len());
+}
+
+#[test]
+pub fn test_materials() {
+    let model = include_bytes!("vox/teapot.vox");
+    let vox_file = parse_vox(model).unwrap();
+
+    let material = vox_file.material(1).unwrap();
+    assert_eq!(material.material_type, MaterialType::Diffuse);
+    assert_eq!(material.rough, Some(0.1));
+    assert_eq!(material.spec, Some(0.5));
+    assert_eq!(material.ior, Some(0.3));
+
+    let model = include_bytes!("vox/monu0.vox");
+    assert!(parse_vox(model).unwrap().material(1).is_none());
+}
+
+#[test]
+pub fn test_flatten_without_scene() {
+    let model = include_bytes!("vox/monu0.vox");
+    let vox_file = parse_vox(model).unwrap();
+
+    assert!(vox_file.scene.is_none());
+    assert_eq!(vox_file.flatten().len(), vox_file.models[0].voxels().len());
+}
+
+#[test]
+pub fn test_round_trip() {
+    let models: [&[u8]; 17] = [
+        include_bytes!("vox/chr_knight.vox"),
+        include_bytes!("vox/menger.vox"),
+        include_bytes!("vox/monu0.vox"),
+        include_bytes!("vox/monu1.vox"),
+        include_bytes!("vox/monu2.vox"),
+        include_bytes!("vox/monu3.vox"),
+        include_bytes!("vox/monu4.vox"),
+        include_bytes!("vox/monu5.vox"),
+        include_bytes!("vox/monu6.vox"),
+        include_bytes!("vox/monu6-without-water.vox"),
+        include_bytes!("vox/monu7.vox"),
+        include_bytes!("vox/monu8.vox"),
+        include_bytes!("vox/monu8-without-water.vox"),
+        include_bytes!("vox/monu9.vox"),
+        include_bytes!("vox/monu10.vox"),
+        include_bytes!("vox/monu16.vox"),
+        include_bytes!("vox/teapot.vox"),
+    ];
+
+    for model in models {
+        let vox_file = parse_vox(model).unwrap();
+        let written = write_vox(&vox_file);
+
+        assert_eq!(parse_vox(&written).unwrap(), vox_file);
+        assert_eq!(write_vox(&parse_vox(&written).unwrap()), written);
+    }
+}
+
+#[test]
+pub fn test_write_modified() {
+    let mut vox_file = VoxFile::default();
+    vox_file.models.push(VoxModel::new(
+        [2, 2, 2],
+        vec![Voxel::new(0, 0, 0, 1), Voxel::new(1, 1, 1, 2)],
+    ));
+    vox_file
+        .models
+        .push(VoxModel::new([1, 1, 1], vec![Voxel::new(0, 0, 0, 3)]));
+    vox_file.materials.insert(
+        2,
+        Material {
+            material_type: MaterialType::Glass,
+            ior: Some(0.5),
+            ..Default::default()
+        },
+    );
+    let mut metal = Material::from_properties(
+        [("_type", "_metal"), ("_rough", "rough")]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .into_iter()
+            .collect(),
+    );
+    metal.metal = Some(0.5);
+    vox_file.materials.insert(3, metal);
+
+    let reparsed = parse_vox(&write_vox(&vox_file)).unwrap();
+
+    assert_eq!(reparsed.models, vox_file.models);
+    assert_eq!(reparsed.palette, Palette::default());
+    assert_eq!(reparsed.materials[&2].material_type, MaterialType::Glass);
+    assert_eq!(reparsed.materials[&2].ior, Some(0.5));
+    assert_eq!(reparsed.materials[&3].metal, Some(0.5));
+    assert_eq!(reparsed.materials[&3].properties["_rough"], "rough");
+    assert_eq!(reparsed.instances().len(), 2);
+}
+
+#[test]
+pub fn test_errors() {
+    let model = include_bytes!("vox/monu0.vox");
+
+    assert_eq!(parse_vox(b"VOX"), Err(VoxError::BadMagic));
+    assert_eq!(parse_vox(b"PNG \x96\0\0\0"), Err(VoxError::BadMagic));
+    assert_eq!(
+        parse_vox(b"VOX \x64\0\0\0"),
+        Err(VoxError::UnsupportedVersion(100))
+    );
+    assert_eq!(
+        parse_vox(b"VOX \x96\0\0\0MAIN"),
+        Err(VoxError::TruncatedChunk("MAIN".to_string()))
+    );
+
+    let mut version_200 = model.to_vec();
+    version_200[4..8].copy_from_slice(&200i32.to_le_bytes());
+    assert!(parse_vox(&version_200).is_ok());
+
+    let mut no_main = model.to_vec();
+    no_main[8..12].copy_from_slice(b"PACK");
+    assert_eq!(parse_vox(&no_main), Err(VoxError::MissingMainChunk));
+
+    // First child of MAIN is the SIZE chunk at offset 20
+    let mut invalid_id = model.to_vec();
+    invalid_id[20] = 0;
+    assert_eq!(
+        parse_vox(&invalid_id),
+        Err(VoxError::InvalidChunkId([0, b'I', b'Z', b'E']))
+    );
+
+    // XYZI chunk follows the 24 byte SIZE chunk
+    let mut count_mismatch = model.to_vec();
+    count_mismatch[56..60].copy_from_slice(&1u32.to_le_bytes());
+    assert!(matches!(
+        parse_vox(&count_mismatch),
+        Err(VoxError::VoxelCountMismatch { expected: 1, .. })
+    ));
+
+    assert_eq!(
+        parse_vox(&model[..model.len() - 1]),
+        Err(VoxError::TruncatedChunk("MAIN".to_string()))
+    );
+}
+
+#[test]
+pub fn test_corrupted_files() {
+    let models: [&[u8]; 3] = [
+        include_bytes!("vox/chr_knight.vox"),
+        include_bytes!("vox/teapot.vox"),
+        include_bytes!("vox/monu0.vox"),
+    ];
+
+    // Deterministic linear congruential generator
+    let mut seed = 0x2545f491u64;
+    let mut random = move |max: usize| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as usize % max
+    };
+
+    for model in models {
+        assert!(parse_vox(model).is_ok());
+
+        // Truncate inside the header and at random positions
+        for length in (0..64).chain((0..64).map(|_| random(model.len()))) {
+            let _ = parse_vox(&model[..length]);
+        }
+
+        // Overwrite random bytes, biased towards the chunk headers at the start
+        for _ in 0..64 {
+            let mut corrupted = model.to_vec();
+
+            for _ in 0..(1 + random(4)) {
+                let range = model.len().min(1 << (6 + random(16)));
+                let position = random(range);
+                corrupted[position] = random(256) as u8;
+            }
+
+            let _ = parse_vox(&corrupted);
+        }
+
+        // Declare huge chunk sizes and element counts
+        for position in (8..512).step_by(4) {
+            let mut corrupted = model.to_vec();
+            corrupted[position..position + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+            let _ = parse_vox(&corrupted);
+        }
+    }
+}
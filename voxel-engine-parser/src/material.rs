@@ -0,0 +1,143 @@
+use crate::dict::{parse_dict, Dict};
+use nom::combinator::map;
+use nom::number::complete::le_i32;
+use nom::sequence::pair;
+use nom::IResult;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialType {
+    #[default]
+    Diffuse,
+    Metal,
+    Glass,
+    Emit,
+    Blend,
+    Media,
+    Cloud,
+}
+
+impl MaterialType {
+    fn from_property(value: &str) -> Self {
+        match value {
+            "_metal" => MaterialType::Metal,
+            "_glass" => MaterialType::Glass,
+            "_emit" => MaterialType::Emit,
+            "_blend" => MaterialType::Blend,
+            "_media" => MaterialType::Media,
+            "_cloud" => MaterialType::Cloud,
+            // Fall back for types introduced by newer MagicaVoxel versions
+            _ => MaterialType::Diffuse,
+        }
+    }
+}
+
+/// Material of a palette entry stored in a `MATL` chunk.
+///
+/// Properties not stored in the chunk or not parsable as numbers are `None`,
+/// all properties including unknown ones are kept in `properties`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Material {
+    pub material_type: MaterialType,
+    pub weight: Option<f32>,
+    pub rough: Option<f32>,
+    pub spec: Option<f32>,
+    pub ior: Option<f32>,
+    pub att: Option<f32>,
+    pub flux: Option<f32>,
+    pub emit: Option<f32>,
+    pub ldr: Option<f32>,
+    pub metal: Option<f32>,
+    pub alpha: Option<f32>,
+    pub trans: Option<f32>,
+    pub density: Option<f32>,
+    pub properties: Dict,
+}
+
+impl Material {
+    pub fn from_properties(properties: Dict) -> Self {
+        let number = |key: &str| parse_number(properties.get(key)?);
+
+        Self {
+            material_type: properties
+                .get("_type")
+                .map_or(MaterialType::Diffuse, |value| {
+                    MaterialType::from_property(value)
+                }),
+            weight: number("_weight"),
+            rough: number("_rough"),
+            spec: number("_spec"),
+            ior: number("_ior"),
+            att: number("_att"),
+            flux: number("_flux"),
+            emit: number("_emit"),
+            ldr: number("_ldr"),
+            metal: number("_metal"),
+            alpha: number("_alpha"),
+            trans: number("_trans"),
+            density: number("_d"),
+            properties,
+        }
+    }
+
+    /// MagicaVoxel stores the index of refraction reduced by one
+    pub fn refractive_index(&self) -> Option<f32> {
+        self.ior.map(|ior| ior + 1.0)
+    }
+}
+
+pub(crate) fn parse_number(value: &str) -> Option<f32> {
+    value.trim().parse().ok()
+}
+
+pub(crate) fn parse_material(input: &[u8]) -> IResult<&[u8], (i32, Material)> {
+    map(pair(le_i32, parse_dict), |(id, properties)| {
+        (id, Material::from_properties(properties))
+    })(input)
+}
+
+#[test]
+fn test_material_properties() {
+    let properties = |entries: &[(&str, &str)]| {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Dict>()
+    };
+
+    let glass = Material::from_properties(properties(&[
+        ("_type", "_glass"),
+        ("_rough", "0.1"),
+        ("_ior", "0.5"),
+        ("_trans", "0.8"),
+    ]));
+    assert_eq!(glass.material_type, MaterialType::Glass);
+    assert_eq!(glass.rough, Some(0.1));
+    assert_eq!(glass.refractive_index(), Some(1.5));
+    assert_eq!(glass.trans, Some(0.8));
+    assert_eq!(glass.emit, None);
+
+    let emit = Material::from_properties(properties(&[
+        ("_type", "_emit"),
+        ("_emit", "0.7"),
+        ("_flux", "2"),
+        ("_ldr", "0"),
+    ]));
+    assert_eq!(emit.material_type, MaterialType::Emit);
+    assert_eq!(emit.emit, Some(0.7));
+    assert_eq!(emit.flux, Some(2.0));
+
+    assert_eq!(
+        Material::from_properties(Dict::new()).material_type,
+        MaterialType::Diffuse
+    );
+
+    // Unparsable numbers are left out without rejecting the material
+    let metal = Material::from_properties(properties(&[
+        ("_type", "_metal"),
+        ("_rough", "rough"),
+        ("_metal", "0.5"),
+    ]));
+    assert_eq!(metal.rough, None);
+    assert_eq!(metal.metal, Some(0.5));
+    assert_eq!(metal.properties["_rough"], "rough");
+}
@@ -0,0 +1,72 @@
+use crate::Palette;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Voxel {
+    x: u8,
+    y: u8,
+    z: u8,
+    color_index: u8,
+}
+
+impl Voxel {
+    pub fn new(x: u8, y: u8, z: u8, color_index: u8) -> Self {
+        Self {
+            x,
+            y,
+            z,
+            color_index,
+        }
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn z(&self) -> u8 {
+        self.z
+    }
+
+    pub fn position(&self) -> [u8; 3] {
+        [self.x, self.y, self.z]
+    }
+
+    pub fn color_index(&self) -> u8 {
+        self.color_index
+    }
+
+    pub fn color(&self, palette: &Palette) -> [u8; 4] {
+        palette.color(self.color_index)
+    }
+}
+
+/// Single model of a vox file, made of a `SIZE` and a `XYZI` chunk
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct VoxModel {
+    size: [u32; 3],
+    voxels: Vec<Voxel>,
+}
+
+impl VoxModel {
+    pub fn new(size: [u32; 3], voxels: Vec<Voxel>) -> Self {
+        Self { size, voxels }
+    }
+
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    pub fn voxels(&self) -> &[Voxel] {
+        &self.voxels
+    }
+}
+
+/// Voxel placed in the world by the scene graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldVoxel {
+    pub position: [i32; 3],
+    pub color_index: u8,
+}
@@ -0,0 +1,97 @@
+const PALETTE_SIZE: usize = 256;
+
+// Channel intensities of the 6x6x6 color cube and the ramps of the default palette
+const CUBE_STEPS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+const RAMP_STEPS: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+/// Palette MagicaVoxel falls back to when a file contains no `RGBA` chunk
+pub const DEFAULT_PALETTE: [[u8; 4]; PALETTE_SIZE] = default_palette();
+
+const fn default_palette() -> [[u8; 4]; PALETTE_SIZE] {
+    // Index 0 is reserved for empty voxels
+    let mut colors = [[0u8; 4]; PALETTE_SIZE];
+    let mut index = 1;
+
+    // Color cube without black, blue varies fastest
+    let mut r = 0;
+    while r < CUBE_STEPS.len() {
+        let mut g = 0;
+        while g < CUBE_STEPS.len() {
+            let mut b = 0;
+            while b < CUBE_STEPS.len() {
+                if index < 216 {
+                    colors[index] = [CUBE_STEPS[r], CUBE_STEPS[g], CUBE_STEPS[b], 0xff];
+                    index += 1;
+                }
+                b += 1;
+            }
+            g += 1;
+        }
+        r += 1;
+    }
+
+    // Red, green, blue and gray ramps
+    let mut step = 0;
+    while step < RAMP_STEPS.len() {
+        let value = RAMP_STEPS[step];
+        colors[216 + step] = [value, 0, 0, 0xff];
+        colors[226 + step] = [0, value, 0, 0xff];
+        colors[236 + step] = [0, 0, value, 0xff];
+        colors[246 + step] = [value, value, value, 0xff];
+        step += 1;
+    }
+
+    colors
+}
+
+/// RGBA colors indexed by the color index stored in each voxel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: [[u8; 4]; PALETTE_SIZE],
+}
+
+impl Palette {
+    /// Create a palette from the contents of an `RGBA` chunk.
+    ///
+    /// The chunk stores the color of index `i` at position `i - 1`,
+    /// so its last entry is never referenced by a voxel.
+    pub fn from_rgba(rgba: &[[u8; 4]]) -> Self {
+        let mut colors = [[0u8; 4]; PALETTE_SIZE];
+
+        for (color, value) in colors[1..].iter_mut().zip(rgba) {
+            *color = *value;
+        }
+
+        Self { colors }
+    }
+
+    pub fn color(&self, color_index: u8) -> [u8; 4] {
+        self.colors[color_index as usize]
+    }
+
+    pub fn colors(&self) -> &[[u8; 4]; PALETTE_SIZE] {
+        &self.colors
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            colors: DEFAULT_PALETTE,
+        }
+    }
+}
+
+#[test]
+fn test_default_palette() {
+    let palette = Palette::default();
+
+    assert_eq!(palette.color(0), [0x00, 0x00, 0x00, 0x00]);
+    assert_eq!(palette.color(1), [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(palette.color(2), [0xff, 0xff, 0xcc, 0xff]);
+    assert_eq!(palette.color(7), [0xff, 0xcc, 0xff, 0xff]);
+    assert_eq!(palette.color(215), [0x00, 0x00, 0x33, 0xff]);
+    assert_eq!(palette.color(216), [0xee, 0x00, 0x00, 0xff]);
+    assert_eq!(palette.color(236), [0x00, 0x00, 0xee, 0xff]);
+    assert_eq!(palette.color(255), [0x11, 0x11, 0x11, 0xff]);
+}
@@ -0,0 +1,488 @@
+use crate::dict::{parse_dict, Dict};
+use nom::combinator::{map, map_opt};
+use nom::multi::length_count;
+use nom::number::complete::{le_i32, le_u32};
+use nom::sequence::{pair, tuple};
+use nom::IResult;
+use std::collections::{HashMap, HashSet};
+
+/// Id of the transform node every scene starts at
+pub const ROOT_NODE_ID: i32 = 0;
+
+/// Rotation packed into a single byte.
+///
+/// Bits 0-1 and 2-3 store the column of the non-zero entry in the first and
+/// second row of the rotation matrix, bits 4-6 store the sign of each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation(u8);
+
+impl Rotation {
+    pub const IDENTITY: Self = Self(0b0000100);
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        let first = byte & 3;
+        let second = (byte >> 2) & 3;
+
+        if first == 3 || second == 3 || first == second || byte & 0x80 != 0 {
+            None
+        } else {
+            Some(Self(byte))
+        }
+    }
+
+    pub fn byte(&self) -> u8 {
+        self.0
+    }
+
+    pub fn matrix(&self) -> [[i32; 3]; 3] {
+        let first = (self.0 & 3) as usize;
+        let second = ((self.0 >> 2) & 3) as usize;
+        let columns = [first, second, 3 - first - second];
+
+        let mut matrix = [[0; 3]; 3];
+        for (row, column) in columns.into_iter().enumerate() {
+            matrix[row][column] = if self.0 & (0x10 << row) != 0 { -1 } else { 1 };
+        }
+
+        matrix
+    }
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Rigid transform on the voxel grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transform {
+    pub rotation: [[i32; 3]; 3],
+    pub translation: [i32; 3],
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0, 0, 0],
+    };
+
+    fn rotate(&self, point: [i32; 3]) -> [i32; 3] {
+        let [x, y, z] = point;
+        self.rotation
+            .map(|row| row[0] * x + row[1] * y + row[2] * z)
+    }
+
+    pub fn apply(&self, point: [i32; 3]) -> [i32; 3] {
+        let rotated = self.rotate(point);
+        [0, 1, 2].map(|i| rotated[i] + self.translation[i])
+    }
+
+    /// Create the transform that applies `child` first and `self` afterwards
+    pub fn combine(&self, child: &Transform) -> Transform {
+        let columns = [0, 1, 2].map(|column| self.rotate(child.rotation.map(|row| row[column])));
+
+        Transform {
+            rotation: [0, 1, 2].map(|row| columns.map(|column| column[row])),
+            translation: self.apply(child.translation),
+        }
+    }
+
+    /// Transform the voxel at `position` of a model with the given `size`.
+    ///
+    /// Models are rotated around their center `size / 2`, the result is the
+    /// minimum corner of the transformed voxel cell.
+    pub fn apply_to_voxel(&self, position: [i32; 3], size: [i32; 3]) -> [i32; 3] {
+        let rotated = self.rotate([0, 1, 2].map(|i| position[i] - size[i] / 2));
+        let flipped = self
+            .rotate([1, 1, 1])
+            .map(|sign| if sign < 0 { 1 } else { 0 });
+
+        [0, 1, 2].map(|i| rotated[i] - flipped[i] + self.translation[i])
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformFrame {
+    pub attributes: Dict,
+    pub rotation: Rotation,
+    pub translation: [i32; 3],
+}
+
+impl TransformFrame {
+    pub(crate) fn from_attributes(attributes: Dict) -> Option<Self> {
+        let rotation = match attributes.get("_r") {
+            Some(value) => Rotation::from_byte(value.trim().parse().ok()?)?,
+            None => Rotation::IDENTITY,
+        };
+
+        let translation = match attributes.get("_t") {
+            Some(value) => {
+                let mut components = value.split_whitespace().map(|c| c.parse::<i32>());
+                let translation = [
+                    components.next()?.ok()?,
+                    components.next()?.ok()?,
+                    components.next()?.ok()?,
+                ];
+
+                if components.next().is_some() {
+                    return None;
+                }
+
+                translation
+            }
+            None => [0, 0, 0],
+        };
+
+        Some(Self {
+            attributes,
+            rotation,
+            translation,
+        })
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform {
+            rotation: self.rotation.matrix(),
+            translation: self.translation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformNode {
+    pub id: i32,
+    pub attributes: Dict,
+    pub child_id: i32,
+    pub layer_id: i32,
+    pub frames: Vec<TransformFrame>,
+}
+
+impl TransformNode {
+    pub fn name(&self) -> Option<&str> {
+        self.attributes.get("_name").map(String::as_str)
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.attributes.get("_hidden").map(String::as_str) == Some("1")
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.frames
+            .first()
+            .map(TransformFrame::transform)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupNode {
+    pub id: i32,
+    pub attributes: Dict,
+    pub child_ids: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeModel {
+    pub model_id: i32,
+    pub attributes: Dict,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeNode {
+    pub id: i32,
+    pub attributes: Dict,
+    pub models: Vec<ShapeModel>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneNode {
+    Transform(TransformNode),
+    Group(GroupNode),
+    Shape(ShapeNode),
+}
+
+impl SceneNode {
+    pub fn id(&self) -> i32 {
+        match self {
+            SceneNode::Transform(node) => node.id,
+            SceneNode::Group(node) => node.id,
+            SceneNode::Shape(node) => node.id,
+        }
+    }
+}
+
+/// Placement of a model in the world
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelInstance {
+    pub model_id: usize,
+    pub layer_id: i32,
+    pub transform: Transform,
+}
+
+/// Scene graph made of transform, group and shape nodes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    nodes: HashMap<i32, SceneNode>,
+}
+
+impl Scene {
+    pub fn new(nodes: impl IntoIterator<Item = SceneNode>) -> Self {
+        Self {
+            nodes: nodes.into_iter().map(|node| (node.id(), node)).collect(),
+        }
+    }
+
+    pub fn node(&self, id: i32) -> Option<&SceneNode> {
+        self.nodes.get(&id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &SceneNode> {
+        self.nodes.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Walk the scene from the root node and collect every visible model instance
+    pub fn instances(&self) -> Vec<ModelInstance> {
+        let mut instances = Vec::new();
+
+        // Every node of a tree is reached exactly once, so visited nodes
+        // can only be reached again through cycles or shared children
+        let mut visited = HashSet::new();
+        let mut stack = vec![(ROOT_NODE_ID, Transform::IDENTITY, 0)];
+
+        while let Some((id, transform, layer_id)) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+
+            match self.nodes.get(&id) {
+                Some(SceneNode::Transform(node)) if !node.hidden() => {
+                    let transform = transform.combine(&node.transform());
+                    stack.push((node.child_id, transform, node.layer_id));
+                }
+                Some(SceneNode::Group(node)) => {
+                    // Push in reverse to visit children in order
+                    for child_id in node.child_ids.iter().rev() {
+                        stack.push((*child_id, transform, layer_id));
+                    }
+                }
+                Some(SceneNode::Shape(node)) => {
+                    let model_ids = node.models.iter().take(1).map(|model| model.model_id);
+
+                    for model_id in model_ids.filter_map(|id| usize::try_from(id).ok()) {
+                        instances.push(ModelInstance {
+                            model_id,
+                            layer_id,
+                            transform,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        instances
+    }
+}
+
+/// Layer referenced by transform nodes, stored in a `LAYR` chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    /// Id referenced by the `layer_id` of transform nodes
+    pub id: i32,
+    /// Attributes like the name `_name` and the visibility `_hidden`
+    pub attributes: Dict,
+}
+
+impl Layer {
+    pub fn name(&self) -> Option<&str> {
+        self.attributes.get("_name").map(String::as_str)
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.attributes.get("_hidden").map(String::as_str) == Some("1")
+    }
+}
+
+fn parse_transform_frame(input: &[u8]) -> IResult<&[u8], TransformFrame> {
+    map_opt(parse_dict, TransformFrame::from_attributes)(input)
+}
+
+pub(crate) fn parse_transform_node(input: &[u8]) -> IResult<&[u8], TransformNode> {
+    map(
+        tuple((
+            le_i32,
+            parse_dict,
+            le_i32,
+            le_i32,
+            le_i32,
+            length_count(le_u32, parse_transform_frame),
+        )),
+        |(id, attributes, child_id, _reserved_id, layer_id, frames)| TransformNode {
+            id,
+            attributes,
+            child_id,
+            layer_id,
+            frames,
+        },
+    )(input)
+}
+
+pub(crate) fn parse_group_node(input: &[u8]) -> IResult<&[u8], GroupNode> {
+    map(
+        tuple((le_i32, parse_dict, length_count(le_u32, le_i32))),
+        |(id, attributes, child_ids)| GroupNode {
+            id,
+            attributes,
+            child_ids,
+        },
+    )(input)
+}
+
+pub(crate) fn parse_shape_node(input: &[u8]) -> IResult<&[u8], ShapeNode> {
+    let model = map(pair(le_i32, parse_dict), |(model_id, attributes)| {
+        ShapeModel {
+            model_id,
+            attributes,
+        }
+    });
+
+    map(
+        tuple((le_i32, parse_dict, length_count(le_u32, model))),
+        |(id, attributes, models)| ShapeNode {
+            id,
+            attributes,
+            models,
+        },
+    )(input)
+}
+
+pub(crate) fn parse_layer(input: &[u8]) -> IResult<&[u8], Layer> {
+    map(
+        tuple((le_i32, parse_dict, le_i32)),
+        |(id, attributes, _reserved_id)| Layer { id, attributes },
+    )(input)
+}
+
+#[test]
+fn test_rotation_matrix() {
+    assert_eq!(Rotation::IDENTITY.matrix(), Transform::IDENTITY.rotation);
+
+    // Rotate 90 degrees around z: x -> -y, y -> x
+    let rotation = Rotation::from_byte(0b0100001).unwrap();
+    assert_eq!(rotation.matrix(), [[0, 1, 0], [-1, 0, 0], [0, 0, 1]]);
+
+    assert_eq!(Rotation::from_byte(0b0000000), None);
+    assert_eq!(Rotation::from_byte(0b0000011), None);
+}
+
+#[test]
+fn test_transform_combine() {
+    let parent = Transform {
+        rotation: Rotation::from_byte(0b0100001).unwrap().matrix(),
+        translation: [10, 0, 0],
+    };
+    let child = Transform {
+        rotation: Transform::IDENTITY.rotation,
+        translation: [1, 2, 3],
+    };
+
+    let combined = parent.combine(&child);
+
+    assert_eq!(
+        combined.apply([0, 0, 0]),
+        parent.apply(child.apply([0, 0, 0]))
+    );
+    assert_eq!(
+        combined.apply([4, 5, 6]),
+        parent.apply(child.apply([4, 5, 6]))
+    );
+}
+
+#[test]
+fn test_apply_to_voxel() {
+    let flip_x = Transform {
+        rotation: [[-1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0, 0, 0],
+    };
+
+    // Voxel cells of a 4 wide model are mirrored around its center
+    assert_eq!(flip_x.apply_to_voxel([0, 0, 0], [4, 1, 1]), [1, 0, 0]);
+    assert_eq!(flip_x.apply_to_voxel([3, 0, 0], [4, 1, 1]), [-2, 0, 0]);
+    assert_eq!(
+        Transform::IDENTITY.apply_to_voxel([3, 0, 0], [4, 1, 1]),
+        [1, 0, 0]
+    );
+}
+
+#[test]
+fn test_scene_instances() {
+    let frame = |translation: &str| {
+        TransformFrame::from_attributes(Dict::from([("_t".to_string(), translation.to_string())]))
+            .unwrap()
+    };
+    let transform = |id, child_id, translation, attributes: Dict| {
+        SceneNode::Transform(TransformNode {
+            id,
+            attributes,
+            child_id,
+            layer_id: 0,
+            frames: vec![frame(translation)],
+        })
+    };
+    let shape = |id, model_id| {
+        SceneNode::Shape(ShapeNode {
+            id,
+            attributes: Dict::new(),
+            models: vec![ShapeModel {
+                model_id,
+                attributes: Dict::new(),
+            }],
+        })
+    };
+
+    let hidden = Dict::from([("_hidden".to_string(), "1".to_string())]);
+    let scene = Scene::new([
+        transform(0, 1, "0 0 0", Dict::new()),
+        SceneNode::Group(GroupNode {
+            id: 1,
+            attributes: Dict::new(),
+            child_ids: vec![2, 4, 6],
+        }),
+        transform(2, 3, "1 2 3", Dict::new()),
+        shape(3, 0),
+        transform(4, 5, "-4 0 0", Dict::new()),
+        shape(5, 1),
+        transform(6, 7, "9 9 9", hidden),
+        shape(7, 2),
+    ]);
+
+    let instances = scene.instances();
+
+    assert_eq!(instances.len(), 2);
+    assert_eq!(instances[0].model_id, 0);
+    assert_eq!(instances[0].transform.translation, [1, 2, 3]);
+    assert_eq!(instances[1].model_id, 1);
+    assert_eq!(instances[1].transform.translation, [-4, 0, 0]);
+}
+
+#[test]
+fn test_scene_cycle() {
+    let scene = Scene::new([SceneNode::Group(GroupNode {
+        id: ROOT_NODE_ID,
+        attributes: Dict::new(),
+        child_ids: vec![ROOT_NODE_ID],
+    })]);
+
+    assert!(scene.instances().is_empty());
+}
@@ -0,0 +1,234 @@
+use crate::material::parse_number;
+use crate::{Dict, Layer, Material, MaterialType, SceneNode, TransformFrame, VoxFile, VoxModel};
+
+const VERSION: i32 = 150;
+
+fn write_i32(output: &mut Vec<u8>, value: i32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_string(output: &mut Vec<u8>, value: &str) {
+    write_u32(output, value.len() as u32);
+    output.extend_from_slice(value.as_bytes());
+}
+
+fn write_dict(output: &mut Vec<u8>, dict: &Dict) {
+    // Sort entries to keep the output deterministic
+    let mut entries = dict.iter().collect::<Vec<_>>();
+    entries.sort();
+
+    write_u32(output, entries.len() as u32);
+    for (key, value) in entries {
+        write_string(output, key);
+        write_string(output, value);
+    }
+}
+
+fn write_chunk(output: &mut Vec<u8>, id: &str, content: &[u8], children: &[u8]) {
+    output.extend_from_slice(id.as_bytes());
+    write_u32(output, content.len() as u32);
+    write_u32(output, children.len() as u32);
+    output.extend_from_slice(content);
+    output.extend_from_slice(children);
+}
+
+fn set_number(properties: &mut Dict, key: &str, value: Option<f32>) {
+    // Unparsable values are `None` as well, they are kept as they are
+    let is_number = properties
+        .get(key)
+        .and_then(|value| parse_number(value))
+        .is_some();
+
+    match value {
+        Some(value) => {
+            properties.insert(key.to_string(), value.to_string());
+        }
+        None if is_number => {
+            properties.remove(key);
+        }
+        None => {}
+    }
+}
+
+fn material_properties(material: &Material) -> Dict {
+    let mut properties = material.properties.clone();
+
+    // Keep the stored properties unless the typed fields were changed
+    let without_properties = |material: Material| Material {
+        properties: Dict::new(),
+        ..material
+    };
+    let current = without_properties(Material::from_properties(properties.clone()));
+    if current == without_properties(material.clone()) {
+        return properties;
+    }
+
+    let material_type = match material.material_type {
+        MaterialType::Diffuse => "_diffuse",
+        MaterialType::Metal => "_metal",
+        MaterialType::Glass => "_glass",
+        MaterialType::Emit => "_emit",
+        MaterialType::Blend => "_blend",
+        MaterialType::Media => "_media",
+        MaterialType::Cloud => "_cloud",
+    };
+    properties.insert("_type".to_string(), material_type.to_string());
+
+    set_number(&mut properties, "_weight", material.weight);
+    set_number(&mut properties, "_rough", material.rough);
+    set_number(&mut properties, "_spec", material.spec);
+    set_number(&mut properties, "_ior", material.ior);
+    set_number(&mut properties, "_att", material.att);
+    set_number(&mut properties, "_flux", material.flux);
+    set_number(&mut properties, "_emit", material.emit);
+    set_number(&mut properties, "_ldr", material.ldr);
+    set_number(&mut properties, "_metal", material.metal);
+    set_number(&mut properties, "_alpha", material.alpha);
+    set_number(&mut properties, "_trans", material.trans);
+    set_number(&mut properties, "_d", material.density);
+
+    properties
+}
+
+fn frame_attributes(frame: &TransformFrame) -> Dict {
+    let mut attributes = frame.attributes.clone();
+
+    // Keep the stored attributes unless rotation or translation were changed
+    let current = TransformFrame::from_attributes(attributes.clone());
+
+    if current.as_ref().map(|current| current.rotation) != Some(frame.rotation) {
+        attributes.insert("_r".to_string(), frame.rotation.byte().to_string());
+    }
+
+    if current.as_ref().map(|current| current.translation) != Some(frame.translation) {
+        let [x, y, z] = frame.translation;
+        attributes.insert("_t".to_string(), format!("{x} {y} {z}"));
+    }
+
+    attributes
+}
+
+fn write_model(output: &mut Vec<u8>, model: &VoxModel) {
+    let mut content = Vec::new();
+    for extent in model.size() {
+        write_u32(&mut content, extent);
+    }
+    write_chunk(output, "SIZE", &content, &[]);
+
+    let mut content = Vec::with_capacity(4 + model.voxels().len() * 4);
+    write_u32(&mut content, model.voxels().len() as u32);
+    for voxel in model.voxels() {
+        content.extend_from_slice(&[voxel.x(), voxel.y(), voxel.z(), voxel.color_index()]);
+    }
+    write_chunk(output, "XYZI", &content, &[]);
+}
+
+fn write_scene_node(output: &mut Vec<u8>, node: &SceneNode) {
+    let mut content = Vec::new();
+
+    match node {
+        SceneNode::Transform(node) => {
+            write_i32(&mut content, node.id);
+            write_dict(&mut content, &node.attributes);
+            write_i32(&mut content, node.child_id);
+            write_i32(&mut content, -1);
+            write_i32(&mut content, node.layer_id);
+            write_u32(&mut content, node.frames.len() as u32);
+            for frame in &node.frames {
+                write_dict(&mut content, &frame_attributes(frame));
+            }
+
+            write_chunk(output, "nTRN", &content, &[]);
+        }
+        SceneNode::Group(node) => {
+            write_i32(&mut content, node.id);
+            write_dict(&mut content, &node.attributes);
+            write_u32(&mut content, node.child_ids.len() as u32);
+            for child_id in &node.child_ids {
+                write_i32(&mut content, *child_id);
+            }
+
+            write_chunk(output, "nGRP", &content, &[]);
+        }
+        SceneNode::Shape(node) => {
+            write_i32(&mut content, node.id);
+            write_dict(&mut content, &node.attributes);
+            write_u32(&mut content, node.models.len() as u32);
+            for model in &node.models {
+                write_i32(&mut content, model.model_id);
+                write_dict(&mut content, &model.attributes);
+            }
+
+            write_chunk(output, "nSHP", &content, &[]);
+        }
+    }
+}
+
+fn write_layer(output: &mut Vec<u8>, layer: &Layer) {
+    let mut content = Vec::new();
+    write_i32(&mut content, layer.id);
+    write_dict(&mut content, &layer.attributes);
+    write_i32(&mut content, -1);
+
+    write_chunk(output, "LAYR", &content, &[]);
+}
+
+/// Serialize a vox file.
+///
+/// Materials, scene graph and layers are only written if the file contains them.
+pub fn write_vox(vox_file: &VoxFile) -> Vec<u8> {
+    let mut children = Vec::new();
+
+    if vox_file.models.len() > 1 {
+        let mut content = Vec::new();
+        write_u32(&mut content, vox_file.models.len() as u32);
+        write_chunk(&mut children, "PACK", &content, &[]);
+    }
+
+    for model in &vox_file.models {
+        write_model(&mut children, model);
+    }
+
+    if let Some(scene) = &vox_file.scene {
+        let mut nodes = scene.nodes().collect::<Vec<_>>();
+        nodes.sort_by_key(|node| node.id());
+
+        for node in nodes {
+            write_scene_node(&mut children, node);
+        }
+    }
+
+    for layer in &vox_file.layers {
+        write_layer(&mut children, layer);
+    }
+
+    // The chunk stores the color of index `i` at position `i - 1`
+    let mut content = Vec::with_capacity(256 * 4);
+    for color in vox_file.palette.colors()[1..]
+        .iter()
+        .chain([[0u8; 4]].iter())
+    {
+        content.extend_from_slice(color);
+    }
+    write_chunk(&mut children, "RGBA", &content, &[]);
+
+    let mut materials = vox_file.materials.iter().collect::<Vec<_>>();
+    materials.sort_by_key(|(index, _)| **index);
+    for (index, material) in materials {
+        let mut content = Vec::new();
+        write_i32(&mut content, *index as i32);
+        write_dict(&mut content, &material_properties(material));
+        write_chunk(&mut children, "MATL", &content, &[]);
+    }
+
+    let mut output = Vec::with_capacity(20 + children.len());
+    output.extend_from_slice(b"VOX ");
+    write_i32(&mut output, VERSION);
+    write_chunk(&mut output, "MAIN", &[], &children);
+
+    output
+}
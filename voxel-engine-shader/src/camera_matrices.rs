@@ -14,7 +14,7 @@ impl CameraMatrices {
     pub fn new(view: &Mat4, projection: &Mat4) -> Self {
         let inverse_view = view.inverse();
         let inverse_projection = projection.inverse();
-        let mut inverse_centered_view = inverse_view.clone();
+        let mut inverse_centered_view = inverse_view;
 
         inverse_centered_view.col_mut(3).x = 0.0;
         inverse_centered_view.col_mut(3).y = 0.0;
@@ -0,0 +1,116 @@
+use crate::{hash, OctreeHit, TraversalStats};
+use bytemuck::{Pod, Zeroable};
+use glam::{vec3, Vec3};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+
+/// Iterations shown as the hottest color of the heatmap
+const MAX_ITERATIONS: f32 = 128.0;
+/// Distance shown as the farthest color of the depth view
+const MAX_DISTANCE: f32 = 8.0;
+/// Number of levels of the traversal stack
+const STACK_SIZE: f32 = 23.0;
+
+/// Render mode of `main_cs`, laid out to match its push constants
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Zeroable, Pod)]
+pub struct DebugView {
+    /// One of the `DebugView::*` modes
+    pub mode: u32,
+}
+
+impl DebugView {
+    /// Regular shading without any debug view
+    pub const SHADED: u32 = 0;
+    /// Heatmap of the number of traversal iterations
+    pub const ITERATIONS: u32 = 1;
+    /// Distance of the hit
+    pub const DEPTH: u32 = 2;
+    /// Normal of the hit face
+    pub const NORMALS: u32 = 3;
+    /// Octree level of the hit voxel
+    pub const LEVEL: u32 = 4;
+    /// Color hashed from the index of the node storing the hit voxel
+    pub const NODE_INDEX: u32 = 5;
+    /// Heatmap of the deepest stack level used by the traversal
+    pub const STACK_DEPTH: u32 = 6;
+    /// Number of modes including the shaded one
+    pub const COUNT: u32 = 7;
+}
+
+/// Blue to red color ramp of a value from zero to one
+pub fn heat(value: f32) -> Vec3 {
+    let value = value.clamp(0.0, 1.0) * 4.0;
+    vec3(
+        (1.5 - (value - 3.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (value - 2.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (value - 1.0).abs()).clamp(0.0, 1.0),
+    )
+}
+
+/// Distinct color of an integer
+pub fn hash_color(value: u32) -> Vec3 {
+    let hash = hash(value);
+    vec3(
+        (hash & 0xff) as f32,
+        ((hash >> 8) & 0xff) as f32,
+        ((hash >> 16) & 0xff) as f32,
+    ) / 255.0
+}
+
+/// Color of a pixel in a debug view, views of the hit are black where the ray misses
+pub fn debug_color(hit: &OctreeHit, stats: &TraversalStats, view: &DebugView) -> Vec3 {
+    if view.mode == DebugView::ITERATIONS {
+        return heat(stats.iterations as f32 / MAX_ITERATIONS);
+    }
+    if view.mode == DebugView::STACK_DEPTH {
+        return heat(stats.max_stack_depth as f32 / STACK_SIZE);
+    }
+
+    if !hit.is_hit() {
+        return Vec3::ZERO;
+    }
+
+    if view.mode == DebugView::DEPTH {
+        heat(hit.distance / MAX_DISTANCE)
+    } else if view.mode == DebugView::NORMALS {
+        hit.normal * 0.5 + 0.5
+    } else if view.mode == DebugView::LEVEL {
+        hash_color(hit.depth)
+    } else {
+        hash_color(hit.node_index)
+    }
+}
+
+#[test]
+fn test_debug_color() {
+    let hit = OctreeHit {
+        distance: 2.0,
+        normal: -Vec3::X,
+        node_index: 3,
+        depth: 4,
+        ..OctreeHit::default()
+    };
+    let stats = TraversalStats {
+        iterations: 20,
+        max_stack_depth: 4,
+    };
+    let color = |hit: &OctreeHit, mode: u32| debug_color(hit, &stats, &DebugView { mode });
+
+    assert_eq!(color(&hit, DebugView::NORMALS), vec3(0.0, 0.5, 0.5));
+    assert_eq!(color(&hit, DebugView::NODE_INDEX), hash_color(3));
+    assert_ne!(hash_color(3), hash_color(4));
+    assert_eq!(color(&OctreeHit::MISS, DebugView::DEPTH), Vec3::ZERO);
+
+    // Traversal views also show rays missing the octree
+    assert_eq!(
+        color(&OctreeHit::MISS, DebugView::ITERATIONS),
+        color(&hit, DebugView::ITERATIONS)
+    );
+    assert_ne!(color(&OctreeHit::MISS, DebugView::STACK_DEPTH), Vec3::ZERO);
+
+    // The heatmap goes from blue to red
+    assert_eq!(heat(0.0).max_element(), heat(0.0).z);
+    assert_eq!(heat(1.0).max_element(), heat(1.0).x);
+    assert!(heat(0.5).y > heat(0.5).x && heat(0.5).y > heat(0.5).z);
+}
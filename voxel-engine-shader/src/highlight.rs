@@ -0,0 +1,116 @@
+use crate::{OctreeHit, OctreeNode, OctreeTraversal, Ray};
+use bytemuck::{Pod, Zeroable};
+use glam::{uvec3, vec3, UVec3, Vec3};
+
+/// Color mixed into the highlighted voxel
+const HIGHLIGHT_COLOR: Vec3 = vec3(1.0, 0.8, 0.2);
+/// Fraction of the highlight color in the shaded color
+const HIGHLIGHT_AMOUNT: f32 = 0.4;
+
+/// Voxel highlighted in the render, laid out to match the std140 uniform block of the shader.
+/// The coordinates are scalars, since a `UVec3` is 16 bytes wide in SPIR-V but 12 on the host.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Zeroable, Pod)]
+pub struct Highlight {
+    /// Coordinates of the voxel in the grid of the deepest level, like `OctreeHit::voxel`
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+    /// Zero if no voxel is highlighted
+    pub active: u32,
+}
+
+impl Highlight {
+    pub fn voxel(voxel: UVec3) -> Self {
+        Self {
+            x: voxel.x,
+            y: voxel.y,
+            z: voxel.z,
+            active: 1,
+        }
+    }
+}
+
+/// Tint the shaded color of a hit if it is the highlighted voxel
+pub fn highlight_color(color: Vec3, hit: &OctreeHit, highlight: &Highlight) -> Vec3 {
+    if highlight.active == 0 || hit.voxel != uvec3(highlight.x, highlight.y, highlight.z) {
+        return color;
+    }
+
+    color.lerp(HIGHLIGHT_COLOR, HIGHLIGHT_AMOUNT)
+}
+
+/// Tint the color of a pixel if its ray hits the highlighted voxel, for colors averaged over
+/// many rays. The ray is only traced while a voxel is highlighted.
+pub fn highlight_pixel(
+    color: Vec3,
+    ray: &Ray,
+    octree: &[OctreeNode],
+    highlight: &Highlight,
+    traversal: impl OctreeTraversal,
+) -> Vec3 {
+    if highlight.active == 0 {
+        return color;
+    }
+
+    let hit = traversal.trace(ray, octree);
+    if !hit.is_hit() {
+        return color;
+    }
+
+    highlight_color(color, &hit, highlight)
+}
+
+#[test]
+fn test_highlight_color() {
+    let hit = OctreeHit {
+        voxel: UVec3::new(3, 1, 4),
+        ..OctreeHit::default()
+    };
+    let color = Vec3::splat(0.5);
+
+    assert_eq!(highlight_color(color, &hit, &Highlight::default()), color);
+    assert_eq!(
+        highlight_color(color, &hit, &Highlight::voxel(UVec3::new(3, 1, 5))),
+        color
+    );
+
+    let highlighted = highlight_color(color, &hit, &Highlight::voxel(hit.voxel));
+    assert!(
+        (highlighted - vec3(0.7, 0.62, 0.38)).length() < 1e-6,
+        "{highlighted}"
+    );
+}
+
+#[test]
+fn test_highlight_pixel() {
+    use crate::{trace_octree_hit, Octree, StackTraversal};
+
+    let octree = Octree::from_voxels([glam::IVec3::ZERO, glam::IVec3::X]).unwrap();
+    let center = octree.voxel_to_world(glam::IVec3::ZERO) + octree.voxel_size() / 2.0;
+    let ray = Ray {
+        origin: vec3(-3.0, center.y, center.z),
+        direction: Vec3::X,
+    };
+    let hit = trace_octree_hit(&ray, &octree.nodes);
+    assert!(hit.is_hit());
+    let color = Vec3::splat(0.5);
+    let pixel = |ray: &Ray, highlight: &Highlight| {
+        highlight_pixel(color, ray, &octree.nodes, highlight, StackTraversal)
+    };
+
+    let highlight = Highlight::voxel(hit.voxel);
+    assert_eq!(
+        pixel(&ray, &highlight),
+        highlight_color(color, &hit, &highlight)
+    );
+    assert_ne!(pixel(&ray, &highlight), color);
+    assert_eq!(pixel(&ray, &Highlight::default()), color);
+
+    // Rays missing the octree are not tinted, even with the voxel at the origin of the grid
+    let miss = Ray {
+        origin: ray.origin,
+        direction: -Vec3::X,
+    };
+    assert_eq!(pixel(&miss, &Highlight::voxel(UVec3::ZERO)), color);
+}
@@ -1,33 +1,117 @@
 #![no_std]
 #![feature(const_fn_floating_point_arithmetic)]
 
+#[cfg(not(target_arch = "spirv"))]
+extern crate alloc;
+
 mod camera_matrices;
+mod debug;
+mod highlight;
 mod intersect;
+mod lighting;
+mod material;
 mod octree;
+mod path_tracing;
+mod random;
 mod ray;
 mod sky;
 mod stack;
 
 pub use camera_matrices::*;
+pub use debug::*;
 pub use glam;
+pub use highlight::*;
 pub use intersect::*;
+pub use lighting::*;
+pub use material::*;
 pub use octree::*;
+pub use path_tracing::*;
+pub use random::*;
 pub use ray::*;
 pub use sky::*;
 pub use stack::*;
 
-use glam::{
-    vec3, IVec2, Mat4, UVec2, UVec3, Vec2, Vec2Swizzles, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles,
-};
-use spirv_std::num_traits::Float;
+use glam::{vec2, UVec2, UVec3, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
 use spirv_std::{spirv, Image};
 
-#[spirv(compute(threads(16, 16)))]
-pub fn main_cs(
-    #[spirv(global_invocation_id)] id: UVec3,
-    #[spirv(descriptor_set = 0, binding = 0)] image: &Image!(2D, format = rgba32f, sampled = false),
-    #[spirv(descriptor_set = 0, binding = 1, uniform)] camera: &CameraMatrices,
-    #[spirv(descriptor_set = 0, binding = 2, storage_buffer)] octree: &[OctreeNode],
+type OutputImage = Image!(2D, format = rgba32f, sampled = false);
+
+/// Color of a pixel shaded with the sun, sky and ambient occlusion, the highlighted voxel is tinted
+#[allow(clippy::too_many_arguments)]
+pub fn shade_pixel(
+    output_coords: UVec2,
+    screen_size: UVec2,
+    camera: &CameraMatrices,
+    octree: &[OctreeNode],
+    attributes: &[VoxelAttribute],
+    materials: &[Material],
+    sun: &Sun,
+    sky: &Sky,
+    environment: &[Vec4],
+    ambient_occlusion_settings: &AmbientOcclusion,
+    highlight: &Highlight,
+    traversal: impl OctreeTraversal,
+) -> Vec3 {
+    let screen_coords = output_coords.as_vec2() / screen_size.as_vec2() * 2.0 - 1.0;
+    let camera_ray = camera.create_ray(screen_coords);
+    let hit = traversal.trace(&camera_ray, octree);
+    if !hit.is_hit() {
+        return sky_color(&camera_ray, sky, sun, environment);
+    }
+
+    let mut random = Random::new(output_coords.y * screen_size.x + output_coords.x);
+    let occlusion = ambient_occlusion(
+        &hit,
+        octree,
+        ambient_occlusion_settings,
+        &mut random,
+        traversal,
+    );
+    let sky_light = sky_color(
+        &Ray {
+            origin: hit.position,
+            direction: hit.normal,
+        },
+        sky,
+        sun,
+        environment,
+    );
+    let ambient = occlusion * sun.ambient * sky_light;
+
+    let attribute = hit.attribute(octree, attributes);
+    let emission = attribute.color() * Material::of(attribute, materials).emission;
+    let color = emission + shade_hit(&hit, octree, attributes, sun, ambient, traversal);
+    highlight_color(color, &hit, highlight)
+}
+
+/// Color of a pixel in a debug view of the stack based octree traversal
+pub fn debug_pixel(
+    output_coords: UVec2,
+    screen_size: UVec2,
+    camera: &CameraMatrices,
+    octree: &[OctreeNode],
+    debug_view: &DebugView,
+) -> Vec3 {
+    let screen_coords = output_coords.as_vec2() / screen_size.as_vec2() * 2.0 - 1.0;
+    let camera_ray = camera.create_ray(screen_coords);
+    let (hit, stats) = trace_octree_stats(&camera_ray, octree);
+    debug_color(&hit, &stats, debug_view)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render(
+    id: UVec3,
+    image: &OutputImage,
+    camera: &CameraMatrices,
+    octree: &[OctreeNode],
+    attributes: &[VoxelAttribute],
+    materials: &[Material],
+    sun: &Sun,
+    sky: &Sky,
+    environment: &[Vec4],
+    ambient_occlusion_settings: &AmbientOcclusion,
+    highlight: &Highlight,
+    traversal: impl OctreeTraversal,
 ) {
     let output_coords = id.xy();
     let screen_size: UVec2 = image.query_size();
@@ -36,11 +120,249 @@ pub fn main_cs(
         return;
     }
 
-    let screen_coords = output_coords.as_vec2() / screen_size.as_vec2() * 2.0 - 1.0;
+    let output_color = shade_pixel(
+        output_coords,
+        screen_size,
+        camera,
+        octree,
+        attributes,
+        materials,
+        sun,
+        sky,
+        environment,
+        ambient_occlusion_settings,
+        highlight,
+        traversal,
+    );
+
+    unsafe {
+        image.write(output_coords, Vec4::from((output_color, 1.0)));
+    }
+}
+
+/// Render a debug view of the stack based octree traversal
+fn render_debug(
+    id: UVec3,
+    image: &OutputImage,
+    camera: &CameraMatrices,
+    octree: &[OctreeNode],
+    debug_view: &DebugView,
+) {
+    let output_coords = id.xy();
+    let screen_size: UVec2 = image.query_size();
+
+    if output_coords.x >= screen_size.x || output_coords.y >= screen_size.y {
+        return;
+    }
+
+    let output_color = debug_pixel(output_coords, screen_size, camera, octree, debug_view);
+
+    unsafe {
+        image.write(output_coords, Vec4::from((output_color, 1.0)));
+    }
+}
+
+/// Progressively render with the path tracer, averaging the frames in the accumulation buffer.
+/// The highlighted voxel is tinted in the shown average, so it does not restart the average.
+#[allow(clippy::too_many_arguments)]
+fn render_path_trace(
+    id: UVec3,
+    image: &OutputImage,
+    camera: &CameraMatrices,
+    octree: &[OctreeNode],
+    attributes: &[VoxelAttribute],
+    materials: &[Material],
+    sun: &Sun,
+    sky: &Sky,
+    environment: &[Vec4],
+    path_tracing: &PathTracing,
+    accumulation: &mut [Vec4],
+    highlight: &Highlight,
+    traversal: impl OctreeTraversal,
+) {
+    let output_coords = id.xy();
+    let screen_size: UVec2 = image.query_size();
+
+    if output_coords.x >= screen_size.x || output_coords.y >= screen_size.y {
+        return;
+    }
+
+    // Every frame uses different random numbers and a jittered position in the pixel
+    let pixel_index = output_coords.y * screen_size.x + output_coords.x;
+    let mut random = Random::new(pixel_index ^ hash(path_tracing.frame));
+    let jitter = vec2(random.next_f32(), random.next_f32());
+
+    let screen_coords = (output_coords.as_vec2() + jitter) / screen_size.as_vec2() * 2.0 - 1.0;
     let camera_ray = camera.create_ray(screen_coords);
-    let output_color = trace_octree(&camera_ray, octree);
+    let color = trace_path(
+        &camera_ray,
+        octree,
+        attributes,
+        materials,
+        sun,
+        sky,
+        environment,
+        path_tracing.bounces,
+        &mut random,
+        traversal,
+    );
+
+    let sum = if path_tracing.frame == 0 {
+        Vec4::from((color, 1.0))
+    } else {
+        accumulation[pixel_index as usize] + Vec4::from((color, 1.0))
+    };
+    accumulation[pixel_index as usize] = sum;
+
+    // The ray without jitter gives the highlight sharp edges
+    let screen_coords = output_coords.as_vec2() / screen_size.as_vec2() * 2.0 - 1.0;
+    let output_color = highlight_pixel(
+        sum.xyz() / sum.w,
+        &camera.create_ray(screen_coords),
+        octree,
+        highlight,
+        traversal,
+    );
 
     unsafe {
         image.write(output_coords, Vec4::from((output_color, 1.0)));
     }
 }
+
+/// Render using the stack based octree traversal, or show one of its debug views
+#[spirv(compute(threads(16, 16)))]
+#[allow(clippy::too_many_arguments)]
+pub fn main_cs(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &OutputImage,
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] camera: &CameraMatrices,
+    #[spirv(descriptor_set = 0, binding = 2, storage_buffer)] octree: &[OctreeNode],
+    #[spirv(descriptor_set = 0, binding = 3, storage_buffer)] attributes: &[VoxelAttribute],
+    #[spirv(descriptor_set = 0, binding = 4, uniform)] sun: &Sun,
+    #[spirv(descriptor_set = 0, binding = 5, uniform)] ambient_occlusion: &AmbientOcclusion,
+    #[spirv(descriptor_set = 0, binding = 8, storage_buffer)] materials: &[Material],
+    #[spirv(descriptor_set = 0, binding = 9, storage_buffer)] environment: &[Vec4],
+    #[spirv(descriptor_set = 0, binding = 10, uniform)] sky: &Sky,
+    #[spirv(descriptor_set = 0, binding = 11, uniform)] highlight: &Highlight,
+    #[spirv(push_constant)] debug_view: &DebugView,
+) {
+    if debug_view.mode != DebugView::SHADED {
+        render_debug(id, image, camera, octree, debug_view);
+        return;
+    }
+
+    render(
+        id,
+        image,
+        camera,
+        octree,
+        attributes,
+        materials,
+        sun,
+        sky,
+        environment,
+        ambient_occlusion,
+        highlight,
+        StackTraversal,
+    );
+}
+
+/// Render using the ESVO octree traversal
+#[spirv(compute(threads(16, 16)))]
+#[allow(clippy::too_many_arguments)]
+pub fn main_cs_esvo(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &OutputImage,
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] camera: &CameraMatrices,
+    #[spirv(descriptor_set = 0, binding = 2, storage_buffer)] octree: &[OctreeNode],
+    #[spirv(descriptor_set = 0, binding = 3, storage_buffer)] attributes: &[VoxelAttribute],
+    #[spirv(descriptor_set = 0, binding = 4, uniform)] sun: &Sun,
+    #[spirv(descriptor_set = 0, binding = 5, uniform)] ambient_occlusion: &AmbientOcclusion,
+    #[spirv(descriptor_set = 0, binding = 8, storage_buffer)] materials: &[Material],
+    #[spirv(descriptor_set = 0, binding = 9, storage_buffer)] environment: &[Vec4],
+    #[spirv(descriptor_set = 0, binding = 10, uniform)] sky: &Sky,
+    #[spirv(descriptor_set = 0, binding = 11, uniform)] highlight: &Highlight,
+) {
+    render(
+        id,
+        image,
+        camera,
+        octree,
+        attributes,
+        materials,
+        sun,
+        sky,
+        environment,
+        ambient_occlusion,
+        highlight,
+        EsvoTraversal,
+    );
+}
+
+/// Path trace using the stack based octree traversal
+#[spirv(compute(threads(16, 16)))]
+#[allow(clippy::too_many_arguments)]
+pub fn main_cs_path_trace(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &OutputImage,
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] camera: &CameraMatrices,
+    #[spirv(descriptor_set = 0, binding = 2, storage_buffer)] octree: &[OctreeNode],
+    #[spirv(descriptor_set = 0, binding = 3, storage_buffer)] attributes: &[VoxelAttribute],
+    #[spirv(descriptor_set = 0, binding = 4, uniform)] sun: &Sun,
+    #[spirv(descriptor_set = 0, binding = 6, uniform)] path_tracing: &PathTracing,
+    #[spirv(descriptor_set = 0, binding = 7, storage_buffer)] accumulation: &mut [Vec4],
+    #[spirv(descriptor_set = 0, binding = 8, storage_buffer)] materials: &[Material],
+    #[spirv(descriptor_set = 0, binding = 9, storage_buffer)] environment: &[Vec4],
+    #[spirv(descriptor_set = 0, binding = 10, uniform)] sky: &Sky,
+    #[spirv(descriptor_set = 0, binding = 11, uniform)] highlight: &Highlight,
+) {
+    render_path_trace(
+        id,
+        image,
+        camera,
+        octree,
+        attributes,
+        materials,
+        sun,
+        sky,
+        environment,
+        path_tracing,
+        accumulation,
+        highlight,
+        StackTraversal,
+    );
+}
+
+/// Path trace using the ESVO octree traversal
+#[spirv(compute(threads(16, 16)))]
+#[allow(clippy::too_many_arguments)]
+pub fn main_cs_path_trace_esvo(
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &OutputImage,
+    #[spirv(descriptor_set = 0, binding = 1, uniform)] camera: &CameraMatrices,
+    #[spirv(descriptor_set = 0, binding = 2, storage_buffer)] octree: &[OctreeNode],
+    #[spirv(descriptor_set = 0, binding = 3, storage_buffer)] attributes: &[VoxelAttribute],
+    #[spirv(descriptor_set = 0, binding = 4, uniform)] sun: &Sun,
+    #[spirv(descriptor_set = 0, binding = 6, uniform)] path_tracing: &PathTracing,
+    #[spirv(descriptor_set = 0, binding = 7, storage_buffer)] accumulation: &mut [Vec4],
+    #[spirv(descriptor_set = 0, binding = 8, storage_buffer)] materials: &[Material],
+    #[spirv(descriptor_set = 0, binding = 9, storage_buffer)] environment: &[Vec4],
+    #[spirv(descriptor_set = 0, binding = 10, uniform)] sky: &Sky,
+    #[spirv(descriptor_set = 0, binding = 11, uniform)] highlight: &Highlight,
+) {
+    render_path_trace(
+        id,
+        image,
+        camera,
+        octree,
+        attributes,
+        materials,
+        sun,
+        sky,
+        environment,
+        path_tracing,
+        accumulation,
+        highlight,
+        EsvoTraversal,
+    );
+}
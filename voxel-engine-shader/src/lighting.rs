@@ -0,0 +1,207 @@
+use crate::{OctreeHit, OctreeNode, OctreeTraversal, Random, Ray, VoxelAttribute};
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec3, Vec4, Vec4Swizzles};
+
+/// Directional light, laid out to match the std140 uniform block of the shader.
+/// Vectors are stored as `Vec4`, since a `Vec3` is 16 bytes wide in SPIR-V but 12 on the host.
+#[repr(C)]
+#[derive(Copy, Clone, Zeroable, Pod)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+pub struct Sun {
+    /// Normalized direction pointing towards the sun, w is unused
+    pub direction: Vec4,
+    /// Color of the sun light, w is unused
+    pub color: Vec4,
+    pub intensity: f32,
+    /// Fraction of the sky light reaching surfaces as ambient light
+    pub ambient: f32,
+    /// Pads the block to a multiple of 16 bytes
+    pub padding: Vec2,
+}
+
+impl Default for Sun {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::new(0.5, 1.0, 0.3).normalize().extend(0.0),
+            color: Vec4::new(1.0, 0.95, 0.85, 0.0),
+            intensity: 1.0,
+            ambient: 0.3,
+            padding: Vec2::ZERO,
+        }
+    }
+}
+
+/// Settings of the ambient occlusion, laid out to match the uniform block of the shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Zeroable, Pod)]
+pub struct AmbientOcclusion {
+    /// Number of rays per hit, zero disables the occlusion
+    pub samples: u32,
+    /// Maximum distance of occluding voxels, in voxels
+    pub radius: f32,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            samples: 8,
+            radius: 4.0,
+        }
+    }
+}
+
+/// Fraction of cosine weighted rays from the hit that escape within the occlusion radius
+pub fn ambient_occlusion(
+    hit: &OctreeHit,
+    octree: &[OctreeNode],
+    settings: &AmbientOcclusion,
+    random: &mut Random,
+    traversal: impl OctreeTraversal,
+) -> f32 {
+    if settings.samples == 0 {
+        return 1.0;
+    }
+
+    let origin = hit.position + hit.normal * hit.voxel_size() * 1e-3;
+    let radius = settings.radius * hit.voxel_size();
+
+    let mut occluded = 0;
+    let mut sample = 0;
+    while sample < settings.samples {
+        let ray = Ray {
+            origin,
+            direction: random.cosine_direction(hit.normal),
+        };
+        if traversal.trace(&ray, octree).distance < radius {
+            occluded += 1;
+        }
+        sample += 1;
+    }
+
+    1.0 - occluded as f32 / settings.samples as f32
+}
+
+/// Lambert shading of a hit, with a shadow ray traced towards the sun.
+/// The ambient light reaches the hit regardless of shadows.
+pub fn shade_hit(
+    hit: &OctreeHit,
+    octree: &[OctreeNode],
+    attributes: &[VoxelAttribute],
+    sun: &Sun,
+    ambient: Vec3,
+    traversal: impl OctreeTraversal,
+) -> Vec3 {
+    let albedo = hit.attribute(octree, attributes).color();
+
+    let lambert = hit.normal.dot(sun.direction.xyz()).max(0.0);
+    if lambert <= 0.0 {
+        return albedo * ambient;
+    }
+
+    // Start slightly in front of the face to not hit the voxel itself
+    let shadow_ray = Ray {
+        origin: hit.position + hit.normal * hit.voxel_size() * 1e-3,
+        direction: sun.direction.xyz(),
+    };
+    if traversal.trace(&shadow_ray, octree).is_hit() {
+        return albedo * ambient;
+    }
+
+    albedo * (ambient + sun.color.xyz() * sun.intensity * lambert)
+}
+
+#[test]
+fn test_shade_hit() {
+    use crate::{trace_octree_hit, Octree, StackTraversal};
+    use glam::IVec3;
+
+    // A floor with a roof floating above its center
+    let floor = (0..16 * 16).map(|i| IVec3::new(i % 16, 0, i / 16));
+    let roof = (0..8 * 8).map(|i| IVec3::new(4 + i % 8, 8, 4 + i / 8));
+    let octree = Octree::from_voxel_attributes(
+        floor
+            .chain(roof)
+            .map(|voxel| (voxel, VoxelAttribute::new([200, 200, 200], 1))),
+    )
+    .unwrap();
+
+    let sun = Sun {
+        direction: Vec4::Y,
+        ..Sun::default()
+    };
+
+    let ambient = Vec3::splat(0.2);
+    let shade_below = |voxel: IVec3| {
+        let ray = Ray {
+            origin: octree.voxel_to_world(voxel) + octree.voxel_size() / 2.0,
+            direction: -Vec3::Y,
+        };
+        let hit = trace_octree_hit(&ray, &octree.nodes);
+        assert_eq!(hit.normal, Vec3::Y);
+
+        shade_hit(
+            &hit,
+            &octree.nodes,
+            &octree.attributes,
+            &sun,
+            ambient,
+            StackTraversal,
+        )
+    };
+
+    let lit = shade_below(IVec3::new(1, 4, 1));
+    let shadowed = shade_below(IVec3::new(8, 4, 8));
+    let roof = shade_below(IVec3::new(8, 12, 8));
+
+    let albedo = VoxelAttribute::new([200, 200, 200], 1).color();
+    assert_eq!(shadowed, albedo * ambient);
+    assert!(lit.x > shadowed.x + 0.5);
+    assert_eq!(lit, roof);
+}
+
+#[test]
+fn test_ambient_occlusion() {
+    use crate::{trace_octree_hit, Octree, StackTraversal};
+    use glam::IVec3;
+
+    // A floor with a pit enclosed by walls in one corner
+    let floor = (0..16 * 16).map(|i| IVec3::new(i % 16, 0, i / 16));
+    let walls = (0..5 * 5 * 4)
+        .map(|i| IVec3::new(i % 5, 1 + i / 25, (i / 5) % 5))
+        .filter(|voxel| voxel.x % 4 == 0 || voxel.z % 4 == 0);
+    let octree = Octree::from_voxels(floor.chain(walls)).unwrap();
+
+    let occlusion_below = |voxel: IVec3, settings: &AmbientOcclusion| {
+        let ray = Ray {
+            origin: octree.voxel_to_world(voxel) + octree.voxel_size() / 2.0,
+            direction: -Vec3::Y,
+        };
+        let hit = trace_octree_hit(&ray, &octree.nodes);
+
+        let mut random = Random::new(7);
+        ambient_occlusion(&hit, &octree.nodes, settings, &mut random, StackTraversal)
+    };
+
+    let settings = AmbientOcclusion {
+        samples: 64,
+        ..AmbientOcclusion::default()
+    };
+    let open = occlusion_below(IVec3::new(12, 3, 12), &settings);
+    let pit = occlusion_below(IVec3::new(2, 3, 2), &settings);
+
+    assert_eq!(open, 1.0);
+    assert!(pit < 0.5, "{pit}");
+
+    // Walls out of reach do not occlude
+    let short = AmbientOcclusion {
+        radius: 0.5,
+        ..settings
+    };
+    assert_eq!(occlusion_below(IVec3::new(2, 3, 2), &short), 1.0);
+
+    let disabled = AmbientOcclusion {
+        samples: 0,
+        ..settings
+    };
+    assert_eq!(occlusion_below(IVec3::new(2, 3, 2), &disabled), 1.0);
+}
@@ -0,0 +1,274 @@
+use crate::{OctreeHit, OctreeNode, OctreeTraversal, Random, Ray, VoxelAttribute};
+use bytemuck::{Pod, Zeroable};
+use glam::{vec3, Vec3};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+
+/// Maximum number of voxels a ray passes inside glass before it is considered trapped
+const MAX_GLASS_STEPS: u32 = 64;
+
+/// Surface of the voxels of a palette entry, laid out to match the storage buffer of the shader
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Zeroable, Pod)]
+pub struct Material {
+    /// One of the `Material::*` kinds
+    pub kind: u32,
+    /// Blur of metal reflections, zero is a perfect mirror
+    pub roughness: f32,
+    /// Index of refraction of glass
+    pub ior: f32,
+    /// Emitted radiance relative to the voxel color
+    pub emission: f32,
+}
+
+impl Material {
+    pub const DIFFUSE: u32 = 0;
+    pub const METAL: u32 = 1;
+    pub const GLASS: u32 = 2;
+    pub const EMISSIVE: u32 = 3;
+
+    pub fn diffuse() -> Self {
+        Self::default()
+    }
+
+    pub fn metal(roughness: f32) -> Self {
+        Self {
+            kind: Self::METAL,
+            roughness,
+            ..Self::default()
+        }
+    }
+
+    pub fn glass(ior: f32) -> Self {
+        Self {
+            kind: Self::GLASS,
+            ior,
+            ..Self::default()
+        }
+    }
+
+    /// Diffuse surface that also emits light
+    pub fn emissive(strength: f32) -> Self {
+        Self {
+            kind: Self::EMISSIVE,
+            emission: strength,
+            ..Self::default()
+        }
+    }
+
+    /// Material of a voxel, looked up by the palette index of its attribute
+    pub fn of(attribute: VoxelAttribute, materials: &[Material]) -> Self {
+        materials[attribute.palette_index() as usize]
+    }
+}
+
+/// Mirror a direction at a surface
+pub fn reflect(direction: Vec3, normal: Vec3) -> Vec3 {
+    direction - 2.0 * direction.dot(normal) * normal
+}
+
+/// Bend a direction at a surface whose normal faces against it, `eta` is the ratio of the
+/// refractive indices. Zero on total internal reflection.
+///
+/// Directions that may not exist are zero instead of an `Option`, rust-gpu can not cast
+/// options of vectors.
+pub fn refract(direction: Vec3, normal: Vec3, eta: f32) -> Vec3 {
+    let cos_i = -direction.dot(normal);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        return Vec3::ZERO;
+    }
+
+    eta * direction + (eta * cos_i - k.sqrt()) * normal
+}
+
+/// Schlick's approximation of the fraction of light reflected by a surface
+pub fn fresnel(cos: f32, ior: f32) -> f32 {
+    let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+}
+
+/// Direction of a ray reflected by a metal surface, zero if it is absorbed
+pub fn reflect_metal(
+    direction: Vec3,
+    normal: Vec3,
+    material: &Material,
+    random: &mut Random,
+) -> Vec3 {
+    let reflected =
+        (reflect(direction, normal) + random.unit_vector() * material.roughness).normalize();
+
+    // Rough reflections can point into the surface
+    if reflected.dot(normal) <= 0.0 {
+        return Vec3::ZERO;
+    }
+
+    reflected
+}
+
+/// Follow a ray hitting glass through the connected glass voxels, choosing between reflection
+/// and refraction at every surface. Returns the ray leaving the glass, its direction is zero
+/// if it is absorbed by an opaque voxel inside the glass or trapped by internal reflections.
+#[allow(clippy::too_many_arguments)]
+pub fn scatter_glass(
+    ray: &Ray,
+    hit: &OctreeHit,
+    material: &Material,
+    octree: &[OctreeNode],
+    attributes: &[VoxelAttribute],
+    materials: &[Material],
+    random: &mut Random,
+    traversal: impl OctreeTraversal,
+) -> Ray {
+    let epsilon = hit.voxel_size() * 1e-3;
+
+    // Reflect off the outside of the surface
+    let refracted = refract(ray.direction, hit.normal, 1.0 / material.ior);
+    let cos_i = -ray.direction.dot(hit.normal);
+    if refracted == Vec3::ZERO || random.next_f32() < fresnel(cos_i, material.ior) {
+        return Ray {
+            origin: hit.position + hit.normal * epsilon,
+            direction: reflect(ray.direction, hit.normal),
+        };
+    }
+    let mut direction = refracted;
+    let mut origin = hit.position - hit.normal * epsilon;
+
+    let mut step = 0;
+    while step < MAX_GLASS_STEPS {
+        // The ray starts inside a glass voxel, which is hit at distance zero
+        let voxel = traversal.trace(&Ray { origin, direction }, octree);
+        if voxel.distance != 0.0 {
+            return Ray { origin, direction };
+        }
+
+        // Find the face the ray leaves the voxel through
+        let corner = voxel.voxel_center() + direction.signum() * voxel.voxel_size() * 0.5;
+        let t = (corner - origin) / direction;
+        let t_exit = t.min_element();
+        let normal = if t.x == t_exit {
+            vec3(direction.x.signum(), 0.0, 0.0)
+        } else if t.y == t_exit {
+            vec3(0.0, direction.y.signum(), 0.0)
+        } else {
+            vec3(0.0, 0.0, direction.z.signum())
+        };
+        let position = origin + direction * t_exit;
+
+        // Keep going through adjacent glass, opaque voxels absorb the ray
+        let next = traversal.trace(
+            &Ray {
+                origin: position + normal * epsilon,
+                direction,
+            },
+            octree,
+        );
+        if next.distance == 0.0 {
+            let attribute = next.attribute(octree, attributes);
+            if Material::of(attribute, materials).kind != Material::GLASS {
+                return Ray {
+                    origin: position,
+                    direction: Vec3::ZERO,
+                };
+            }
+
+            origin = position + normal * epsilon;
+            step += 1;
+            continue;
+        }
+
+        // Leave the glass or reflect back inside
+        let refracted = refract(direction, -normal, material.ior);
+        if refracted != Vec3::ZERO
+            && random.next_f32() >= fresnel(refracted.dot(normal), material.ior)
+        {
+            return Ray {
+                origin: position + normal * epsilon,
+                direction: refracted,
+            };
+        }
+        direction = reflect(direction, normal);
+        origin = position - normal * epsilon;
+        step += 1;
+    }
+
+    Ray {
+        origin,
+        direction: Vec3::ZERO,
+    }
+}
+
+#[test]
+fn test_refract() {
+    // Perpendicular rays are not bent
+    let refracted = refract(-Vec3::Y, Vec3::Y, 1.0 / 1.5);
+    assert!((refracted + Vec3::Y).length() < 1e-6);
+
+    // Snell's law
+    let direction = vec3(1.0, -1.0, 0.0).normalize();
+    let refracted = refract(direction, Vec3::Y, 1.0 / 1.5);
+    assert!((refracted.length() - 1.0).abs() < 1e-6);
+    assert!((refracted.x * 1.5 - direction.x).abs() < 1e-6);
+
+    // Total internal reflection when leaving glass at a flat angle
+    assert_eq!(refract(direction, Vec3::Y, 1.5), Vec3::ZERO);
+
+    assert!((fresnel(1.0, 1.5) - 0.04).abs() < 1e-6);
+    assert_eq!(fresnel(0.0, 1.5), 1.0);
+    assert_eq!(
+        reflect(direction, Vec3::Y),
+        vec3(direction.x, -direction.y, 0.0)
+    );
+}
+
+#[test]
+fn test_scatter_glass() {
+    use crate::{trace_octree_hit, Octree, StackTraversal};
+    use glam::IVec3;
+
+    // A glass slab with a slanted ray entering at the top
+    let mut materials = [Material::diffuse(); 256];
+    materials[1] = Material::glass(1.5);
+    let slab = (0..16 * 16 * 4).map(|i| {
+        let voxel = IVec3::new(i % 16, i / 256, (i / 16) % 16);
+        (voxel, VoxelAttribute::new([255, 255, 255], 1))
+    });
+    let octree = Octree::from_voxel_attributes(slab).unwrap();
+
+    let direction = vec3(0.3, -1.0, 0.0).normalize();
+    let ray = Ray {
+        origin: octree.voxel_to_world(IVec3::new(2, 8, 8)) + 0.5 * octree.voxel_size(),
+        direction,
+    };
+    let hit = trace_octree_hit(&ray, &octree.nodes);
+
+    let mut random = Random::new(11);
+    let mut transmitted = 0;
+    for _ in 0..200 {
+        let scattered = scatter_glass(
+            &ray,
+            &hit,
+            &materials[1],
+            &octree.nodes,
+            &octree.attributes,
+            &materials,
+            &mut random,
+            StackTraversal,
+        );
+        assert_ne!(scattered.direction, Vec3::ZERO);
+
+        if scattered.direction.y > 0.0 {
+            // Reflected at the top
+            assert!((scattered.direction - reflect(direction, Vec3::Y)).length() < 1e-5);
+            assert!(scattered.origin.y > hit.position.y);
+        } else {
+            // Parallel slab faces restore the direction after passing through
+            assert!((scattered.direction - direction).length() < 1e-5);
+            assert!(scattered.origin.y < hit.position.y - 3.9 * octree.voxel_size());
+            transmitted += 1;
+        }
+    }
+
+    // Mostly transmitted at this angle
+    assert!(transmitted > 150, "{transmitted}");
+}
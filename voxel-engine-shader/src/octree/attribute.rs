@@ -0,0 +1,50 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+/// Attributes of a voxel, packing its sRGB color and palette index
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Zeroable, Pod)]
+pub struct VoxelAttribute(pub u32);
+
+impl VoxelAttribute {
+    pub fn new(color: [u8; 3], palette_index: u8) -> Self {
+        Self(
+            color[0] as u32
+                | (color[1] as u32) << 8
+                | (color[2] as u32) << 16
+                | (palette_index as u32) << 24,
+        )
+    }
+
+    pub fn srgb(&self) -> [u8; 3] {
+        [self.0 as u8, (self.0 >> 8) as u8, (self.0 >> 16) as u8]
+    }
+
+    pub fn palette_index(&self) -> u8 {
+        (self.0 >> 24) as u8
+    }
+
+    /// Linear color of the voxel
+    pub fn color(&self) -> Vec3 {
+        let srgb = Vec3::new(
+            (self.0 & 0xff) as f32,
+            ((self.0 >> 8) & 0xff) as f32,
+            ((self.0 >> 16) & 0xff) as f32,
+        ) / 255.0;
+
+        srgb.powf(2.2)
+    }
+}
+
+#[test]
+fn test_voxel_attribute() {
+    let attribute = VoxelAttribute::new([255, 128, 0], 42);
+
+    assert_eq!(attribute.srgb(), [255, 128, 0]);
+    assert_eq!(attribute.palette_index(), 42);
+
+    let color = attribute.color();
+    assert_eq!(color.x, 1.0);
+    assert!(color.y > 0.2 && color.y < 0.25);
+    assert_eq!(color.z, 0.0);
+}
@@ -0,0 +1,535 @@
+use crate::{OctreeNode, VoxelAttribute};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use glam::{IVec3, UVec3};
+
+/// Maximum octree depth, so morton codes fit into 64 bits
+pub const MAX_DEPTH: u32 = 21;
+
+/// Maximum offset stored in a child pointer without a far pointer
+const MAX_CHILD_PTR: usize = 0x7fff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OctreeError {
+    /// The voxels span more than `2^MAX_DEPTH` voxels along an axis
+    TooDeep(u32),
+    /// A child pointer does not fit into an octree node
+    TooManyNodes(usize),
+}
+
+impl Display for OctreeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OctreeError::TooDeep(depth) => {
+                write!(f, "octree depth {depth} exceeds maximum of {MAX_DEPTH}")
+            }
+            OctreeError::TooManyNodes(count) => {
+                write!(f, "octree with {count} nodes can not be addressed")
+            }
+        }
+    }
+}
+
+/// Sparse voxel octree covering the unit cube `[-1, 1]^3`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Octree {
+    pub nodes: Vec<OctreeNode>,
+    /// Attributes of the leaves in morton order, found through the nodes of the deepest level
+    pub attributes: Vec<VoxelAttribute>,
+    /// Number of levels below the root, the cube is `2^depth` voxels wide
+    pub depth: u32,
+    /// Position of the input voxel placed at the minimum corner of the cube
+    pub origin: IVec3,
+}
+
+/// Interleave the bits of a position, x is stored in the lowest bit of each octet
+fn morton_code(position: UVec3, depth: u32) -> u64 {
+    let mut code = 0u64;
+
+    for bit in (0..depth).rev() {
+        let child_idx = ((position.x >> bit) & 1)
+            | (((position.y >> bit) & 1) << 1)
+            | (((position.z >> bit) & 1) << 2);
+        code = (code << 3) | child_idx as u64;
+    }
+
+    code
+}
+
+/// Node of a level, its children are stored consecutively in the next level
+#[derive(Clone)]
+struct LevelNode {
+    valid: u8,
+    first_child: usize,
+}
+
+/// Group sorted morton codes into the nodes of each level above the voxels
+fn build_levels(codes: &[u64], depth: u32) -> Vec<Vec<LevelNode>> {
+    (0..depth)
+        .map(|level| {
+            let shift = 3 * (depth - level);
+            let mut nodes = Vec::<LevelNode>::new();
+            let mut prefix = None;
+            let mut previous_child = None;
+            let mut child_count = 0;
+
+            for code in codes {
+                let child = code >> (shift - 3);
+                if previous_child == Some(child) {
+                    continue;
+                }
+                previous_child = Some(child);
+
+                if prefix != Some(child >> 3) {
+                    prefix = Some(child >> 3);
+                    nodes.push(LevelNode {
+                        valid: 0,
+                        first_child: child_count,
+                    });
+                }
+
+                nodes.last_mut().unwrap().valid |= 1 << (child & 7);
+                child_count += 1;
+            }
+
+            nodes
+        })
+        .collect()
+}
+
+/// Child indices of a node with the number of slots used by their descendants
+fn child_sizes(node: &LevelNode, sizes: &[usize]) -> Vec<(usize, usize)> {
+    (0..8)
+        .filter(|child_idx| node.valid & (1 << child_idx) != 0)
+        .zip(node.first_child..)
+        .map(|(child_idx, index)| (child_idx, sizes[index]))
+        .collect()
+}
+
+/// Lay out the descendants of a node behind its child block.
+///
+/// The 8 child slots are followed by far pointer slots for children whose
+/// offset does not fit into a child pointer and the descendants of each child.
+/// Returns the offsets from the child slots to their child blocks, the number
+/// of far pointer slots and the number of slots used in total.
+fn layout_children(children: &[(usize, usize)]) -> ([Option<usize>; 8], usize, usize) {
+    let mut far_count = 0;
+
+    loop {
+        let mut offsets = [None; 8];
+        let mut required = 0;
+        let mut end = 8 + far_count;
+
+        for (child_idx, size) in children {
+            let offset = end - child_idx;
+            if offset > MAX_CHILD_PTR {
+                required += 1;
+            }
+
+            offsets[*child_idx] = Some(offset);
+            end += size;
+        }
+
+        // Far slots push the children further away, repeat until the count is stable
+        if required == far_count {
+            return (offsets, far_count, end);
+        }
+        far_count = required;
+    }
+}
+
+impl Octree {
+    /// Build an octree from voxel positions with default attributes
+    pub fn from_voxels(voxels: impl IntoIterator<Item = IVec3>) -> Result<Self, OctreeError> {
+        Self::from_voxel_attributes(
+            voxels
+                .into_iter()
+                .map(|voxel| (voxel, VoxelAttribute::default())),
+        )
+    }
+
+    /// Build an octree from voxel positions and their attributes.
+    ///
+    /// The voxels are centered in the smallest power of two cube containing them.
+    /// Children of a node are stored as 8 consecutive nodes indexed by child index,
+    /// nodes are laid out depth first. Voxels are leaves of the deepest level,
+    /// if a position occurs multiple times its first attribute is kept. The child
+    /// pointer of a node of the deepest level points to a slot holding the index
+    /// of its first attribute, the attribute of a leaf follows after the leaves
+    /// before it in the leaf mask.
+    pub fn from_voxel_attributes(
+        voxels: impl IntoIterator<Item = (IVec3, VoxelAttribute)>,
+    ) -> Result<Self, OctreeError> {
+        let voxels = voxels.into_iter().collect::<Vec<_>>();
+
+        if voxels.is_empty() {
+            return Ok(Self {
+                nodes: vec![OctreeNode::default()],
+                attributes: Vec::new(),
+                depth: 1,
+                origin: IVec3::ZERO,
+            });
+        }
+
+        let first = voxels[0].0;
+        let min = voxels.iter().fold(first, |min, (voxel, _)| min.min(*voxel));
+        let max = voxels.iter().fold(first, |max, (voxel, _)| max.max(*voxel));
+        let extent = [0, 1, 2].map(|i| max[i] as i64 - min[i] as i64 + 1);
+
+        let max_extent = extent.into_iter().max().unwrap() as u64;
+        let depth = max_extent.next_power_of_two().trailing_zeros().max(1);
+        if depth > MAX_DEPTH {
+            return Err(OctreeError::TooDeep(depth));
+        }
+
+        // Center the voxels inside the cube
+        let padding = extent.map(|extent| (((1i64 << depth) - extent) / 2) as i32);
+        let origin = min - IVec3::from(padding);
+
+        let mut codes = voxels
+            .iter()
+            .map(|(voxel, attribute)| {
+                (morton_code((*voxel - origin).as_uvec3(), depth), *attribute)
+            })
+            .collect::<Vec<_>>();
+        codes.sort_by_key(|(code, _)| *code);
+        codes.dedup_by_key(|(code, _)| *code);
+
+        let (codes, attributes): (Vec<_>, Vec<_>) = codes.into_iter().unzip();
+        Self::from_morton_codes(&codes, attributes, depth, origin)
+    }
+
+    fn from_morton_codes(
+        codes: &[u64],
+        attributes: Vec<VoxelAttribute>,
+        depth: u32,
+        origin: IVec3,
+    ) -> Result<Self, OctreeError> {
+        let levels = build_levels(codes, depth);
+
+        // Number of slots used by the descendants of each node, computed bottom up.
+        // Nodes of the deepest level store their voxels in the leaf mask and use a
+        // single slot for the index of their first attribute.
+        let mut sizes = vec![Vec::new(); levels.len()];
+        sizes[levels.len() - 1] = vec![1; levels[levels.len() - 1].len()];
+        for level in (0..levels.len() - 1).rev() {
+            sizes[level] = levels[level]
+                .iter()
+                .map(|node| layout_children(&child_sizes(node, &sizes[level + 1])).2)
+                .collect();
+        }
+
+        let node_count = 1 + sizes[0][0];
+        if node_count > u32::MAX as usize {
+            return Err(OctreeError::TooManyNodes(node_count));
+        }
+
+        let mut nodes = vec![OctreeNode::default(); node_count];
+
+        // Level, index in level, slot, slot of the child block and slot of the far pointer
+        let mut stack = vec![(0, 0, 0, 1, None)];
+
+        while let Some((level, index, slot, block, far_slot)) = stack.pop() {
+            let node = &levels[level][index];
+            let offset = block - slot;
+
+            if level == levels.len() - 1 {
+                // Leaves of the deepest level are the morton codes themselves, the
+                // attributes of a node start at the index of its first morton code
+                nodes[slot] = OctreeNode::new(offset as u16, false, node.valid, node.valid);
+                nodes[block] = OctreeNode(node.first_child as u32);
+                continue;
+            }
+
+            nodes[slot] = match far_slot {
+                Some(far_slot) => {
+                    nodes[far_slot] = OctreeNode(offset as u32);
+                    OctreeNode::new((far_slot - slot) as u16, true, node.valid, 0)
+                }
+                None => OctreeNode::new(offset as u16, false, node.valid, 0),
+            };
+
+            let children = child_sizes(node, &sizes[level + 1]);
+            let (offsets, _, _) = layout_children(&children);
+
+            let mut far_slots = block + 8..;
+            for (i, (child_idx, _)) in children.iter().enumerate() {
+                let child_slot = block + child_idx;
+                let offset = offsets[*child_idx].expect("valid children have a child block");
+                let far_slot = (offset > MAX_CHILD_PTR).then(|| far_slots.next()).flatten();
+
+                stack.push((
+                    level + 1,
+                    node.first_child + i,
+                    child_slot,
+                    child_slot + offset,
+                    far_slot,
+                ));
+            }
+        }
+
+        Ok(Self {
+            nodes,
+            attributes,
+            depth,
+            origin,
+        })
+    }
+
+    /// Edge length of a voxel in world space
+    pub fn voxel_size(&self) -> f32 {
+        2.0 / (1u32 << self.depth) as f32
+    }
+
+    /// Convert an input voxel position to the minimum corner of the voxel in world space
+    pub fn voxel_to_world(&self, voxel: IVec3) -> glam::Vec3 {
+        (voxel - self.origin).as_vec3() * self.voxel_size() - 1.0
+    }
+
+    /// World space box around all voxels as minimum and maximum corner, `None` if empty
+    pub fn bounds(&self) -> Option<(glam::Vec3, glam::Vec3)> {
+        let mut min = UVec3::splat(u32::MAX);
+        let mut max = UVec3::ZERO;
+        let mut stack = vec![(0usize, UVec3::ZERO)];
+
+        while let Some((index, position)) = stack.pop() {
+            let node = self.nodes[index];
+
+            for child_idx in 0..8 {
+                if !node.valid(child_idx) {
+                    continue;
+                }
+
+                let bits = UVec3::new(
+                    child_idx as u32 & 1,
+                    (child_idx as u32 >> 1) & 1,
+                    child_idx as u32 >> 2,
+                );
+                let child_position = position * 2 + bits;
+
+                if node.leaf(child_idx) {
+                    min = min.min(child_position);
+                    max = max.max(child_position);
+                } else {
+                    stack.push((
+                        node.first_child(index, &self.nodes) + child_idx,
+                        child_position,
+                    ));
+                }
+            }
+        }
+
+        if min.cmpgt(max).any() {
+            return None;
+        }
+
+        let to_world = |position: UVec3| position.as_vec3() * self.voxel_size() - 1.0;
+        Some((to_world(min), to_world(max + 1)))
+    }
+}
+
+#[cfg(test)]
+fn collect_voxels(octree: &Octree) -> Vec<(IVec3, VoxelAttribute)> {
+    let mut voxels = Vec::new();
+    let mut stack = vec![(0usize, UVec3::ZERO, 0u32)];
+
+    while let Some((index, position, level)) = stack.pop() {
+        let node = octree.nodes[index];
+
+        for child_idx in 0..8 {
+            if !node.valid(child_idx) {
+                continue;
+            }
+
+            let bits = UVec3::new(
+                child_idx as u32 & 1,
+                (child_idx as u32 >> 1) & 1,
+                child_idx as u32 >> 2,
+            );
+            let child_position = position * 2 + bits;
+
+            if node.leaf(child_idx) {
+                assert_eq!(level + 1, octree.depth);
+                let first_attribute = octree.nodes[node.first_child(index, &octree.nodes)].0;
+                voxels.push((
+                    child_position.as_ivec3() + octree.origin,
+                    octree.attributes[first_attribute as usize + node.leaf_index(child_idx)],
+                ));
+            } else {
+                assert!(level + 1 < octree.depth);
+                stack.push((
+                    node.first_child(index, &octree.nodes) + child_idx,
+                    child_position,
+                    level + 1,
+                ));
+            }
+        }
+    }
+
+    voxels.sort_by_key(|(voxel, _)| voxel.to_array());
+    voxels
+}
+
+#[cfg(test)]
+fn test_attribute(voxel: IVec3) -> VoxelAttribute {
+    let hash = voxel.x.wrapping_mul(73856093)
+        ^ voxel.y.wrapping_mul(19349663)
+        ^ voxel.z.wrapping_mul(83492791);
+    VoxelAttribute(hash as u32)
+}
+
+#[cfg(test)]
+fn assert_reproduces(voxels: &[IVec3]) {
+    let octree =
+        Octree::from_voxel_attributes(voxels.iter().map(|voxel| (*voxel, test_attribute(*voxel))))
+            .unwrap();
+
+    let mut expected = voxels
+        .iter()
+        .map(|voxel| (*voxel, test_attribute(*voxel)))
+        .collect::<Vec<_>>();
+    expected.sort_by_key(|(voxel, _)| voxel.to_array());
+    expected.dedup();
+
+    assert_eq!(collect_voxels(&octree), expected);
+}
+
+#[test]
+fn test_build_octree() {
+    // Single voxel
+    assert_reproduces(&[IVec3::new(3, -2, 7)]);
+
+    // Non power of two extent with duplicates
+    assert_reproduces(&[
+        IVec3::new(0, 0, 0),
+        IVec3::new(4, 0, 0),
+        IVec3::new(0, 2, 1),
+        IVec3::new(4, 0, 0),
+    ]);
+
+    // Filled cube
+    let cube = (0..27)
+        .map(|i| IVec3::new(i % 3, (i / 3) % 3, i / 9))
+        .collect::<Vec<_>>();
+    assert_reproduces(&cube);
+
+    // Pseudo random sparse voxels
+    let mut seed = 12345u32;
+    let random = (0..500)
+        .map(|_| {
+            let mut next = || {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 16) as i32 % 40
+            };
+            IVec3::new(next(), next(), next())
+        })
+        .collect::<Vec<_>>();
+    assert_reproduces(&random);
+}
+
+#[test]
+fn test_build_empty_octree() {
+    let octree = Octree::from_voxels([]).unwrap();
+
+    assert_eq!(octree.nodes.len(), 1);
+    assert!(collect_voxels(&octree).is_empty());
+}
+
+#[test]
+fn test_octree_bounds() {
+    assert_eq!(Octree::from_voxels([]).unwrap().bounds(), None);
+
+    // A 2x1x3 slab is centered in a cube of 4 voxels
+    let voxels = (0..6).map(|i| IVec3::new(10 + i % 2, -5, 20 + i / 2));
+    let octree = Octree::from_voxels(voxels).unwrap();
+    let (min, max) = octree.bounds().unwrap();
+    assert_eq!(min, octree.voxel_to_world(IVec3::new(10, -5, 20)));
+    assert_eq!(max, octree.voxel_to_world(IVec3::new(12, -4, 23)));
+    assert_eq!(max - min, glam::Vec3::new(1.0, 0.5, 1.5));
+}
+
+#[test]
+fn test_build_octree_layout() {
+    let octree = Octree::from_voxels([IVec3::new(0, 0, 0), IVec3::new(3, 3, 3)]).unwrap();
+
+    assert_eq!(octree.depth, 2);
+    assert_eq!(octree.nodes.len(), 11);
+
+    let root = octree.nodes[0];
+    assert_eq!(root.child_ptr(), 1);
+    assert!(root.valid(0) && root.valid(7) && !root.valid(1));
+    assert!(!root.leaf(0) && !root.leaf(7));
+
+    // Lower and upper child are stored at their child index slot
+    assert!(octree.nodes[1].valid(0) && octree.nodes[1].leaf(0));
+    assert!(octree.nodes[8].valid(7) && octree.nodes[8].leaf(7));
+
+    // The slots behind the child block hold the index of their first attribute
+    assert_eq!(octree.nodes[1].first_child(1, &octree.nodes), 9);
+    assert_eq!(octree.nodes[8].first_child(8, &octree.nodes), 10);
+    assert_eq!(octree.nodes[9].0, 0);
+    assert_eq!(octree.nodes[10].0, 1);
+}
+
+#[test]
+fn test_build_octree_from_vox() {
+    for model in [
+        include_bytes!("../../../voxel-engine-parser/src/vox/chr_knight.vox").as_slice(),
+        include_bytes!("../../../voxel-engine-parser/src/vox/monu0.vox").as_slice(),
+        include_bytes!("../../../voxel-engine-parser/src/vox/monu3.vox").as_slice(),
+    ] {
+        let vox_file = voxel_engine_parser::parse_vox(model).unwrap();
+        let voxels = vox_file.models[0]
+            .voxels()
+            .iter()
+            .map(|voxel| IVec3::from(voxel.position().map(i32::from)))
+            .collect::<Vec<_>>();
+
+        assert_reproduces(&voxels);
+    }
+}
+
+#[test]
+fn test_build_octree_far_pointers() {
+    // Sparse voxels in a large cube give every voxel its own deep branch
+    let mut seed = 6789u32;
+    let voxels = (0..50000)
+        .map(|_| {
+            let mut next = || {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 12) as i32
+            };
+            IVec3::new(next(), next(), next())
+        })
+        .collect::<Vec<_>>();
+
+    let octree =
+        Octree::from_voxel_attributes(voxels.iter().map(|voxel| (*voxel, test_attribute(*voxel))))
+            .unwrap();
+    assert!(octree.nodes.len() > 2_000_000);
+    assert!(octree.nodes.iter().any(|node| node.far()));
+
+    assert_reproduces(&voxels);
+
+    // Both traversals follow the far pointers down to every voxel, rays start inside the voxel
+    let half_size = octree.voxel_size() / 2.0;
+    for voxel in &voxels {
+        let ray = crate::Ray {
+            origin: octree.voxel_to_world(*voxel) + half_size,
+            direction: glam::vec3(0.3, -0.5, 0.8).normalize(),
+        };
+
+        for hit in [
+            crate::trace_octree_hit(&ray, &octree.nodes),
+            crate::trace_octree_esvo(&ray, &octree.nodes),
+        ] {
+            assert_eq!(hit.voxel.as_ivec3() + octree.origin, *voxel);
+            assert_eq!(hit.depth, octree.depth);
+            assert_eq!(
+                hit.attribute(&octree.nodes, &octree.attributes),
+                test_attribute(*voxel)
+            );
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{OctreeHit, OctreeNode, OctreeTraversal, Ray};
+use glam::{uvec2, UVec2, UVec3, Vec3};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+
+const S_MAX: usize = 23; // Maximum scale (number of float mantissa bits)
+
+/// ESVO traversal of `trace_octree_esvo`
+#[derive(Debug, Copy, Clone)]
+pub struct EsvoTraversal;
+
+impl OctreeTraversal for EsvoTraversal {
+    fn trace(self, ray: &Ray, octree: &[OctreeNode]) -> OctreeHit {
+        trace_octree_esvo(ray, octree)
+    }
+}
+
+/// Find the first voxel hit by a ray using the traversal of Laine and Karras,
+/// "Efficient Sparse Voxel Octrees".
+///
+/// The octree is moved to `[1, 2]^3`, so positions can be manipulated through
+/// their float bits, and mirrored so that the ray direction is negative.
+/// Ray size termination and contours are not supported, every hit is a leaf.
+pub fn trace_octree_esvo(ray: &Ray, octree: &[OctreeNode]) -> OctreeHit {
+    let epsilon = (-(S_MAX as f32)).exp2();
+
+    let mut stack = [UVec2::default(); S_MAX + 1]; // Voxel stack
+
+    // Move the octree from [-1, 1] to [1, 2], t-values stay the same
+    let origin = ray.origin * 0.5 + 1.5;
+    let mut direction = ray.direction * 0.5;
+
+    // Get rid of small ray direction components
+    if direction.x.abs() < epsilon {
+        direction.x = direction.x.signum() * epsilon;
+    }
+    if direction.y.abs() < epsilon {
+        direction.y = direction.y.signum() * epsilon;
+    }
+    if direction.z.abs() < epsilon {
+        direction.z = direction.z.signum() * epsilon;
+    }
+
+    // Precompute ray coefficients
+    let tx_coef = 1.0 / -direction.x.abs();
+    let ty_coef = 1.0 / -direction.y.abs();
+    let tz_coef = 1.0 / -direction.z.abs();
+
+    let mut tx_bias = tx_coef * origin.x;
+    let mut ty_bias = ty_coef * origin.y;
+    let mut tz_bias = tz_coef * origin.z;
+
+    // Create octant mask
+    let mut octant_mask = 7usize;
+    if direction.x > 0.0 {
+        octant_mask ^= 1;
+        tx_bias = 3.0 * tx_coef - tx_bias;
+    }
+    if direction.y > 0.0 {
+        octant_mask ^= 2;
+        ty_bias = 3.0 * ty_coef - ty_bias;
+    }
+    if direction.z > 0.0 {
+        octant_mask ^= 4;
+        tz_bias = 3.0 * tz_coef - tz_bias;
+    }
+
+    // Child indices are mirrored along the axes of positive ray directions
+    let mirror_mask = octant_mask ^ 7;
+
+    // active span t-values
+    let mut t_min = (2.0 * tx_coef - tx_bias)
+        .max(2.0 * ty_coef - ty_bias)
+        .max(2.0 * tz_coef - tz_bias)
+        .max(0.0);
+    let mut t_max = (tx_coef - tx_bias)
+        .min(ty_coef - ty_bias)
+        .min(tz_coef - tz_bias);
+
+    // Ray does not intersect or the cube is behind the ray
+    if t_min > t_max {
+        return OctreeHit::MISS;
+    }
+
+    // Initialize to first child of the root
+    let mut parent_idx = 0usize;
+    let mut child_idx = 0usize;
+    let mut position = Vec3::splat(1.0);
+    let mut scale = S_MAX - 1;
+    let mut scale_exp2 = 0.5;
+
+    if 1.5 * tx_coef - tx_bias > t_min {
+        child_idx ^= 1;
+        position.x = 1.5;
+    }
+    if 1.5 * ty_coef - ty_bias > t_min {
+        child_idx ^= 2;
+        position.y = 1.5;
+    }
+    if 1.5 * tz_coef - tz_bias > t_min {
+        child_idx ^= 4;
+        position.z = 1.5;
+    }
+
+    while scale < S_MAX {
+        let node = octree[parent_idx];
+
+        // Determine maximum t-value
+        let tx_corner = position.x * tx_coef - tx_bias;
+        let ty_corner = position.y * ty_coef - ty_bias;
+        let tz_corner = position.z * tz_coef - tz_bias;
+        let tc_max = tx_corner.min(ty_corner).min(tz_corner);
+
+        // Process voxel if bit in valid mask is set and active t-span not empty
+        let child = child_idx ^ mirror_mask;
+
+        if node.valid(child) && t_min <= t_max {
+            // INTERSECT
+            // Intersect active t-span with the cube
+            let tv_max = t_max.min(tc_max);
+            let half = scale_exp2 * 0.5;
+            let tx_center = half * tx_coef + tx_corner;
+            let ty_center = half * ty_coef + ty_corner;
+            let tz_center = half * tz_coef + tz_corner;
+
+            // Descend to first child
+            if t_min <= tv_max {
+                // Terminate if the child is a leaf
+                if node.leaf(child) {
+                    break;
+                }
+
+                // PUSH
+                // Write parent to stack
+                stack[scale] = uvec2(parent_idx as u32, t_max.to_bits());
+
+                // Find child descriptor
+                parent_idx = node.first_child(parent_idx, octree) + child;
+
+                // Select child voxel that ray enters first
+                child_idx = 0;
+                scale -= 1;
+                scale_exp2 = half;
+
+                if tx_center > t_min {
+                    child_idx ^= 1;
+                    position.x += scale_exp2;
+                }
+                if ty_center > t_min {
+                    child_idx ^= 2;
+                    position.y += scale_exp2;
+                }
+                if tz_center > t_min {
+                    child_idx ^= 4;
+                    position.z += scale_exp2;
+                }
+
+                // Update active t-span
+                t_max = tv_max;
+                continue;
+            }
+        }
+
+        // ADVANCE
+        // Step along the ray
+        let mut step_mask = 0usize;
+        if tx_corner <= tc_max {
+            step_mask ^= 1;
+            position.x -= scale_exp2;
+        }
+        if ty_corner <= tc_max {
+            step_mask ^= 2;
+            position.y -= scale_exp2;
+        }
+        if tz_corner <= tc_max {
+            step_mask ^= 4;
+            position.z -= scale_exp2;
+        }
+
+        // Update active t-span
+        t_min = tc_max;
+        child_idx ^= step_mask;
+
+        // Proceed with pop if the bit flips disagree with ray direction
+        if (child_idx & step_mask) != 0 {
+            // POP
+            // Find highest differing bits
+            let mut differing_bits = 0u32;
+            if (step_mask & 1) != 0 {
+                differing_bits |= position.x.to_bits() ^ (position.x + scale_exp2).to_bits();
+            }
+            if (step_mask & 2) != 0 {
+                differing_bits |= position.y.to_bits() ^ (position.y + scale_exp2).to_bits();
+            }
+            if (step_mask & 4) != 0 {
+                differing_bits |= position.z.to_bits() ^ (position.z + scale_exp2).to_bits();
+            }
+
+            // A step too small to change the position has no exponent to pop to, the
+            // subtraction below would underflow. Count it as a miss.
+            if differing_bits == 0 {
+                scale = S_MAX;
+                break;
+            }
+            scale = (((differing_bits as f32).to_bits() >> 23) - 127) as usize;
+
+            // Left the root
+            if scale >= S_MAX {
+                break;
+            }
+            scale_exp2 = f32::from_bits(((scale as i32 - S_MAX as i32 + 127) << 23) as u32);
+
+            // Restore parent voxel from stack
+            let entry = stack[scale];
+            parent_idx = entry.x as usize;
+            t_max = f32::from_bits(entry.y);
+
+            // Round cube position and extract child slot index
+            let shx = position.x.to_bits() >> scale;
+            let shy = position.y.to_bits() >> scale;
+            let shz = position.z.to_bits() >> scale;
+            position.x = f32::from_bits(shx << scale);
+            position.y = f32::from_bits(shy << scale);
+            position.z = f32::from_bits(shz << scale);
+            child_idx = ((shx & 1) | ((shy & 1) << 1) | ((shz & 1) << 2)) as usize;
+        }
+    }
+
+    // Indicate miss if outside the octree
+    if scale >= S_MAX {
+        return OctreeHit::MISS;
+    }
+
+    // The ray enters the voxel at its maximum corner in mirrored space
+    let tx_enter = (position.x + scale_exp2) * tx_coef - tx_bias;
+    let ty_enter = (position.y + scale_exp2) * ty_coef - ty_bias;
+    let tz_enter = (position.z + scale_exp2) * tz_coef - tz_bias;
+    let t_enter = tx_enter.max(ty_enter).max(tz_enter);
+
+    let mut normal = Vec3::ZERO;
+    if tx_enter == t_enter {
+        normal.x = -ray.direction.x.signum();
+    } else if ty_enter == t_enter {
+        normal.y = -ray.direction.y.signum();
+    } else {
+        normal.z = -ray.direction.z.signum();
+    }
+
+    // Undo mirroring of coordinate system
+    if (octant_mask & 1) == 0 {
+        position.x = 3.0 - scale_exp2 - position.x;
+    }
+    if (octant_mask & 2) == 0 {
+        position.y = 3.0 - scale_exp2 - position.y;
+    }
+    if (octant_mask & 4) == 0 {
+        position.z = 3.0 - scale_exp2 - position.z;
+    }
+
+    // The mantissa of a position in [1, 2] holds the voxel coordinates
+    let voxel = UVec3::new(
+        (position.x.to_bits() & 0x7fffff) >> scale,
+        (position.y.to_bits() & 0x7fffff) >> scale,
+        (position.z.to_bits() & 0x7fffff) >> scale,
+    );
+
+    OctreeHit {
+        distance: t_min,
+        position: ray.walk(t_min),
+        normal,
+        voxel,
+        node_index: parent_idx as u32,
+        depth: (S_MAX - scale) as u32,
+    }
+}
+
+#[cfg(test)]
+fn assert_traversals_agree(octree: &crate::Octree, ray_count: usize) -> usize {
+    let mut seed = 2468u32;
+    let mut random = || {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (seed >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+    };
+
+    let mut hits = 0;
+    for i in 0..ray_count {
+        // Alternate rays from outside and inside the octree
+        let origin = Vec3::new(random(), random(), random()) * if i % 2 == 0 { 3.0 } else { 0.9 };
+        let target = Vec3::new(random(), random(), random()) * 0.9;
+        let ray = Ray {
+            origin,
+            direction: (target - origin).normalize(),
+        };
+
+        let expected = crate::trace_octree_hit(&ray, &octree.nodes);
+        let hit = trace_octree_esvo(&ray, &octree.nodes);
+
+        match (hit.is_hit(), expected.is_hit()) {
+            (true, true) => {
+                assert_eq!(hit.voxel, expected.voxel, "{origin} {target}");
+                assert_eq!(hit.node_index, expected.node_index);
+                assert_eq!(hit.depth, expected.depth);
+                assert_eq!(hit.normal, expected.normal, "{origin} {target}");
+                assert!((hit.distance - expected.distance).abs() < 1e-4);
+                assert!((hit.position - expected.position).length() < 1e-4);
+                hits += 1;
+            }
+            (false, false) => {}
+            _ => panic!("traversals disagree on {origin} {target}: {hit:?} {expected:?}"),
+        }
+    }
+
+    hits
+}
+
+#[test]
+fn test_trace_octree_esvo() {
+    let mut seed = 1357u32;
+    let voxels = (0..300)
+        .map(|_| {
+            let mut next = || {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (seed >> 16) as i32 % 32
+            };
+            glam::IVec3::new(next(), next(), next())
+        })
+        .collect::<alloc::vec::Vec<_>>();
+    let octree = crate::Octree::from_voxels(voxels).unwrap();
+
+    assert!(assert_traversals_agree(&octree, 5000) > 1000);
+}
+
+#[test]
+fn test_trace_octree_esvo_models() {
+    for model in [
+        include_bytes!("../../../voxel-engine-parser/src/vox/chr_knight.vox").as_slice(),
+        include_bytes!("../../../voxel-engine-parser/src/vox/monu3.vox").as_slice(),
+    ] {
+        let vox_file = voxel_engine_parser::parse_vox(model).unwrap();
+        let voxels = vox_file.models[0]
+            .voxels()
+            .iter()
+            .map(|voxel| glam::IVec3::from(voxel.position().map(i32::from)));
+        let octree = crate::Octree::from_voxels(voxels).unwrap();
+
+        assert!(assert_traversals_agree(&octree, 3000) > 300);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{OctreeNode, VoxelAttribute};
+use glam::{UVec3, Vec3};
+
+/// Intersection of a ray with a voxel of the octree.
+///
+/// The traversals return `OctreeHit::MISS` rather than an `Option`, since rust-gpu can not
+/// read the payload of an `Option` holding a struct.
+#[derive(Default, Copy, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+pub struct OctreeHit {
+    /// Distance along the ray, zero if the ray starts inside the voxel and infinite on a miss
+    pub distance: f32,
+    /// World position where the ray enters the voxel
+    pub position: Vec3,
+    /// Normal of the face the ray enters the voxel through
+    pub normal: Vec3,
+    /// Coordinates of the voxel in the grid of its level, `2^depth` voxels wide
+    pub voxel: UVec3,
+    /// Index of the node storing the voxel as a leaf
+    pub node_index: u32,
+    /// Level of the voxel below the root
+    pub depth: u32,
+}
+
+impl OctreeHit {
+    /// Ray missing every voxel of the octree
+    pub const MISS: Self = Self {
+        distance: f32::INFINITY,
+        position: Vec3::ZERO,
+        normal: Vec3::ZERO,
+        voxel: UVec3::ZERO,
+        node_index: 0,
+        depth: 0,
+    };
+
+    /// Whether the ray hit a voxel
+    pub fn is_hit(&self) -> bool {
+        self.distance != f32::INFINITY
+    }
+
+    /// Child index of the voxel in its node
+    pub fn child_index(&self) -> usize {
+        ((self.voxel.x & 1) | (self.voxel.y & 1) << 1 | (self.voxel.z & 1) << 2) as usize
+    }
+
+    /// Edge length of the voxel in world space
+    pub fn voxel_size(&self) -> f32 {
+        2.0 / (1u32 << self.depth) as f32
+    }
+
+    /// Center of the voxel in world space
+    pub fn voxel_center(&self) -> Vec3 {
+        (self.voxel.as_vec3() + 0.5) * self.voxel_size() - 1.0
+    }
+
+    /// Attribute of the voxel, its node points to the index of the attribute of its first leaf
+    pub fn attribute(
+        &self,
+        octree: &[OctreeNode],
+        attributes: &[VoxelAttribute],
+    ) -> VoxelAttribute {
+        let index = self.node_index as usize;
+        let node = octree[index];
+        let first_attribute = octree[node.first_child(index, octree)].0 as usize;
+
+        attributes[first_attribute + node.leaf_index(self.child_index())]
+    }
+}
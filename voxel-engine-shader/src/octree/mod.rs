@@ -1,5 +1,15 @@
+mod attribute;
+#[cfg(not(target_arch = "spirv"))]
+mod builder;
+mod esvo;
+mod hit;
 mod node;
 mod traversal;
 
+pub use attribute::*;
+#[cfg(not(target_arch = "spirv"))]
+pub use builder::*;
+pub use esvo::*;
+pub use hit::*;
 pub use node::*;
 pub use traversal::*;
@@ -1,14 +1,19 @@
 use bytemuck::{Pod, Zeroable};
 
+/// Octree node packing a 15 bit child pointer, a far flag and the valid and leaf masks.
+///
+/// The child pointer is relative to the node itself. If the far flag is set it points
+/// to a far pointer slot holding the full 32 bit offset instead. Nodes of the deepest
+/// level point to a slot holding the index of their first attribute.
 #[repr(C)]
-#[derive(Default, Copy, Clone, Zeroable, Pod)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Zeroable, Pod)]
 pub struct OctreeNode(pub u32);
 
 impl OctreeNode {
     pub fn new(child_ptr: u16, far: bool, valid: u8, leaf: u8) -> Self {
         Self(
             (child_ptr as u32) << 17
-                | if far { 1u32 } else { 0u32 }
+                | if far { 1u32 << 16 } else { 0u32 }
                 | (valid as u32) << 8
                 | leaf as u32,
         )
@@ -22,6 +27,17 @@ impl OctreeNode {
         (self.0 & 0x00010000) != 0
     }
 
+    /// Index of the first child slot of the node stored at `index`, following far pointers
+    pub fn first_child(&self, index: usize, octree: &[OctreeNode]) -> usize {
+        let child_ptr = self.child_ptr() as usize;
+
+        if self.far() {
+            index + octree[index + child_ptr].0 as usize
+        } else {
+            index + child_ptr
+        }
+    }
+
     pub fn valid(&self, index: usize) -> bool {
         (self.0 & (1 << (index + 8))) != 0
     }
@@ -29,4 +45,9 @@ impl OctreeNode {
     pub fn leaf(&self, index: usize) -> bool {
         (self.0 & (1 << index)) != 0
     }
+
+    /// Number of leaves before the child at `index`, used to find its attributes
+    pub fn leaf_index(&self, index: usize) -> usize {
+        (self.0 & 0xff & ((1 << index) - 1)).count_ones() as usize
+    }
 }
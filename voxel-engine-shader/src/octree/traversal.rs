@@ -1,7 +1,10 @@
-use crate::{intersect_box, sky_color, OctreeNode, Ray, Stack};
-use glam::{uvec2, vec2, vec3, IVec2, UVec2, Vec2, Vec3};
+use crate::{OctreeHit, OctreeNode, Ray};
+use glam::{vec3, UVec3, Vec3};
+#[cfg(target_arch = "spirv")]
 use spirv_std::num_traits::Float;
 
+const S_MAX: usize = 23; // Maximum scale (number of float mantissa bits)
+
 #[inline(always)]
 pub fn next_child_index(prev_idx: usize, t1_child: &Vec3, t_exit_child: f32) -> (usize, bool) {
     let mut idx = prev_idx;
@@ -78,8 +81,97 @@ fn initial_child_index(t_enter: f32, tm: &Vec3) -> usize {
     idx
 }
 
-pub fn trace_octree(ray: &Ray, octree: &[OctreeNode]) -> Vec3 {
-    const S_MAX: usize = 23; // Maximum scale (number of float mantissa bits)
+/// Create the hit record of a leaf from the child indices of each level
+fn create_hit(
+    ray: &Ray,
+    t0_child: &Vec3,
+    child_stack: &[usize; S_MAX],
+    stack_idx: usize,
+    dir_mask: usize,
+    node_index: usize,
+) -> OctreeHit {
+    let mut voxel = UVec3::ZERO;
+    let mut level = 0;
+    while level <= stack_idx {
+        let idx = child_stack[level] ^ dir_mask;
+        voxel = voxel * 2 + UVec3::new(idx as u32 & 1, (idx as u32 >> 1) & 1, idx as u32 >> 2);
+        level += 1;
+    }
+
+    // The ray enters through the face of the axis with the latest entry distance
+    let t_enter = t0_child.max_element();
+    let mut normal = Vec3::ZERO;
+    if t0_child.x == t_enter {
+        normal.x = -ray.direction.x.signum();
+    } else if t0_child.y == t_enter {
+        normal.y = -ray.direction.y.signum();
+    } else {
+        normal.z = -ray.direction.z.signum();
+    }
+
+    let distance = t_enter.max(0.0);
+
+    OctreeHit {
+        distance,
+        position: ray.walk(distance),
+        normal,
+        voxel,
+        node_index: node_index as u32,
+        depth: stack_idx as u32 + 1,
+    }
+}
+
+/// Work done by the traversal of a single ray, shown by the debug views
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
+pub struct TraversalStats {
+    /// Number of node visits and sibling advances
+    pub iterations: u32,
+    /// Deepest level of the stack in use
+    pub max_stack_depth: u32,
+}
+
+/// Octree traversal used by the shading functions. Traversals are unit types instead of
+/// closures, since calling a closure passes the ray and octree references in a tuple, which
+/// SPIR-V can not hold in logical addressing.
+pub trait OctreeTraversal: Copy {
+    /// Find the first voxel hit by a ray
+    fn trace(self, ray: &Ray, octree: &[OctreeNode]) -> OctreeHit;
+}
+
+/// Stack based traversal of `trace_octree_hit`
+#[derive(Debug, Copy, Clone)]
+pub struct StackTraversal;
+
+impl OctreeTraversal for StackTraversal {
+    fn trace(self, ray: &Ray, octree: &[OctreeNode]) -> OctreeHit {
+        trace_octree_hit(ray, octree)
+    }
+}
+
+/// Find the first voxel hit by a ray, the octree spans `[-1, 1]^3`
+pub fn trace_octree_hit(ray: &Ray, octree: &[OctreeNode]) -> OctreeHit {
+    trace_octree_stats(ray, octree).0
+}
+
+/// Find the first voxel hit by a ray and count the work done by the traversal
+pub fn trace_octree_stats(ray: &Ray, octree: &[OctreeNode]) -> (OctreeHit, TraversalStats) {
+    let epsilon = (-(S_MAX as f32)).exp2();
+
+    // Get rid of small ray direction components, their t-values would become NaN
+    let mut direction = ray.direction;
+    if direction.x.abs() < epsilon {
+        direction.x = direction.x.signum() * epsilon;
+    }
+    if direction.y.abs() < epsilon {
+        direction.y = direction.y.signum() * epsilon;
+    }
+    if direction.z.abs() < epsilon {
+        direction.z = direction.z.signum() * epsilon;
+    }
+    let ray = &Ray {
+        origin: ray.origin,
+        direction,
+    };
 
     // Calculate intersection points
     let (t0, t1) = intersect_cube(ray, &vec3(0.0, 0.0, 0.0), 1.0);
@@ -87,9 +179,11 @@ pub fn trace_octree(ray: &Ray, octree: &[OctreeNode]) -> Vec3 {
     // Calculate intersection distances
     let (t_enter, t_exit) = (t0.max_element(), t1.min_element());
 
-    // Ray does not intersect
-    if t_enter > t_exit {
-        return sky_color(&ray);
+    let mut stats = TraversalStats::default();
+
+    // Ray does not intersect or the cube is behind the ray
+    if t_enter > t_exit || t_exit < 0.0 {
+        return (OctreeHit::MISS, stats);
     }
 
     let mut index_stack = [0; S_MAX];
@@ -113,54 +207,269 @@ pub fn trace_octree(ray: &Ray, octree: &[OctreeNode]) -> Vec3 {
         dir_mask |= 4;
     }
 
-    let mut child_idx = 0;
-    'main: while stack_idx < S_MAX {
+    // Child index the ray is currently in, stored per level to resume after a pop
+    let mut child_stack = [0; S_MAX];
+    let mut child_idx = initial_child_index(t_enter, &((t0 + t1) / 2.0));
+
+    loop {
+        stats.iterations += 1;
+        stats.max_stack_depth = stats.max_stack_depth.max(stack_idx as u32 + 1);
+
         let node = octree[index_stack[stack_idx]];
         let t0 = t0_stack[stack_idx];
         let t1 = t1_stack[stack_idx];
 
-        // Calculate intersection distances
-        let (t_enter, t_exit) = (t0.max_element(), t1.min_element());
-
         // Calculate middle of ray in cube
         let tm = (t0 + t1) / 2.0;
+        let (t0_child, mut t1_child) = child_span(child_idx, &t0, &tm, &t1);
 
-        // Get child index that ray will enter first
-        child_idx = initial_child_index(t_enter, &tm);
-        let mut exit_node = false;
-
-        // Advance through all siblings
-        while !exit_node {
-            if node.valid(child_idx ^ dir_mask) {
-                if node.leaf(child_idx ^ dir_mask) {
-                    break 'main;
-                } else {
-                    // Push to stack
-                    stack_idx += 1;
-                    index_stack[stack_idx] = node.child_ptr() as usize + (child_idx ^ dir_mask);
-                    (t0_stack[stack_idx], t1_stack[stack_idx]) =
-                        child_span(child_idx, &t0, &tm, &t1);
-                    continue 'main;
-                }
+        // Skip children behind the ray origin
+        if node.valid(child_idx ^ dir_mask) && t1_child.min_element() >= 0.0 {
+            if node.leaf(child_idx ^ dir_mask) {
+                child_stack[stack_idx] = child_idx;
+                let hit = create_hit(
+                    ray,
+                    &t0_child,
+                    &child_stack,
+                    stack_idx,
+                    dir_mask,
+                    index_stack[stack_idx],
+                );
+                return (hit, stats);
             }
 
-            let (t0_child, t1_child) = child_span(child_idx, &t0, &tm, &t1);
-            let t_exit_child = t1_child.min_element();
-            (child_idx, exit_node) = next_child_index(child_idx, &t1_child, t_exit_child);
+            // PUSH
+            let first_child = node.first_child(index_stack[stack_idx], octree);
+            child_stack[stack_idx] = child_idx;
+            stack_idx += 1;
+            index_stack[stack_idx] = first_child + (child_idx ^ dir_mask);
+            t0_stack[stack_idx] = t0_child;
+            t1_stack[stack_idx] = t1_child;
+
+            child_idx = initial_child_index(t0_child.max_element(), &((t0_child + t1_child) / 2.0));
+            continue;
         }
 
-        // POP
-        if stack_idx > 0 {
+        // ADVANCE to the next sibling, popping while the ray exits the current node
+        loop {
+            stats.iterations += 1;
+            let (next_idx, exit_node) =
+                next_child_index(child_idx, &t1_child, t1_child.min_element());
+
+            if !exit_node {
+                child_idx = next_idx;
+                break;
+            }
+
+            // POP
+            if stack_idx == 0 {
+                return (OctreeHit::MISS, stats);
+            }
             stack_idx -= 1;
-        } else {
-            return sky_color(ray);
+            child_idx = child_stack[stack_idx];
+
+            let t0 = t0_stack[stack_idx];
+            let t1 = t1_stack[stack_idx];
+            t1_child = child_span(child_idx, &t0, &((t0 + t1) / 2.0), &t1).1;
         }
     }
+}
+
+#[cfg(test)]
+struct ExpectedHit {
+    distance: f32,
+    normal: Vec3,
+    voxel: glam::IVec3,
+    attribute: crate::VoxelAttribute,
+}
+
+#[cfg(test)]
+fn trace_voxels(
+    ray: &Ray,
+    octree: &crate::Octree,
+    voxels: &[(glam::IVec3, crate::VoxelAttribute)],
+) -> Option<ExpectedHit> {
+    let half_size = octree.voxel_size() / 2.0;
+
+    let mut nearest: Option<ExpectedHit> = None;
+    for (voxel, attribute) in voxels {
+        let center = octree.voxel_to_world(*voxel) + half_size;
+        let (t0, t1) = intersect_cube(ray, &center, half_size);
+        let (t_enter, t_exit) = (t0.max_element(), t1.min_element());
+
+        if t_enter <= t_exit
+            && t_exit >= 0.0
+            && nearest
+                .as_ref()
+                .map_or(true, |hit| t_enter.max(0.0) < hit.distance)
+        {
+            let normal = if t0.x == t_enter {
+                vec3(-ray.direction.x.signum(), 0.0, 0.0)
+            } else if t0.y == t_enter {
+                vec3(0.0, -ray.direction.y.signum(), 0.0)
+            } else {
+                vec3(0.0, 0.0, -ray.direction.z.signum())
+            };
+
+            nearest = Some(ExpectedHit {
+                distance: t_enter.max(0.0),
+                normal,
+                voxel: *voxel,
+                attribute: *attribute,
+            });
+        }
+    }
+
+    nearest
+}
+
+#[cfg(test)]
+fn assert_hit(ray: &Ray, octree: &crate::Octree, expected: &ExpectedHit) {
+    let hit = trace_octree_hit(ray, &octree.nodes);
+    assert!(hit.is_hit(), "ray misses the octree");
+
+    assert_eq!(
+        hit.voxel.as_ivec3() + octree.origin,
+        expected.voxel,
+        "{} {}",
+        ray.origin,
+        ray.direction
+    );
+    assert_eq!(hit.depth, octree.depth);
+    assert_eq!(
+        hit.attribute(&octree.nodes, &octree.attributes),
+        expected.attribute
+    );
+    assert!((hit.distance - expected.distance).abs() < 1e-4);
+    assert!((hit.position - ray.walk(expected.distance)).length() < 1e-4);
+    assert!(
+        (hit.voxel_center() - octree.voxel_to_world(expected.voxel)).length()
+            <= octree.voxel_size()
+    );
+
+    if expected.distance > 0.0 {
+        assert_eq!(hit.normal, expected.normal);
+    }
+}
+
+#[test]
+fn test_trace_octree() {
+    let mut seed = 4321u32;
+    let mut random = || {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (seed >> 8) as f32 / (1 << 24) as f32
+    };
+
+    let mut positions = (0..200)
+        .map(|_| (vec3(random(), random(), random()) * 16.0).as_ivec3())
+        .collect::<alloc::vec::Vec<_>>();
+    positions.sort_by_key(|position| position.to_array());
+    positions.dedup();
+
+    let voxels = positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| {
+            (
+                *position,
+                crate::VoxelAttribute::new([i as u8, 255 - i as u8, 128], i as u8),
+            )
+        })
+        .collect::<alloc::vec::Vec<_>>();
+    let octree = crate::Octree::from_voxel_attributes(voxels.iter().copied()).unwrap();
+
+    let mut hits = 0;
+    for _ in 0..500 {
+        // Rays from outside and inside the octree
+        let origin = (vec3(random(), random(), random()) * 2.0 - 1.0) * 3.0;
+        let target = (vec3(random(), random(), random()) * 2.0 - 1.0) * 0.9;
+        let ray = Ray {
+            origin,
+            direction: (target - origin).normalize(),
+        };
+
+        match trace_voxels(&ray, &octree, &voxels) {
+            Some(expected) => {
+                assert_hit(&ray, &octree, &expected);
+                hits += 1;
+            }
+            None => assert_eq!(trace_octree_hit(&ray, &octree.nodes), OctreeHit::MISS),
+        }
+    }
+
+    assert!(hits > 100);
+}
+
+#[test]
+fn test_trace_octree_far_pointers() {
+    let vox_file = voxel_engine_parser::parse_vox(include_bytes!(
+        "../../../voxel-engine-parser/src/vox/monu3.vox"
+    ))
+    .unwrap();
+
+    // Give every voxel a unique attribute
+    let voxels = vox_file.models[0]
+        .voxels()
+        .iter()
+        .enumerate()
+        .map(|(i, voxel)| {
+            let position = glam::IVec3::from(voxel.position().map(i32::from));
+            (position, crate::VoxelAttribute(i as u32))
+        })
+        .collect::<alloc::vec::Vec<_>>();
+
+    let octree = crate::Octree::from_voxel_attributes(voxels.iter().copied()).unwrap();
+    assert!(octree.nodes.iter().any(|node| node.far()));
+
+    // A ray starting inside a voxel has to hit it first
+    let half_size = octree.voxel_size() / 2.0;
+    for (voxel, attribute) in voxels {
+        let ray = Ray {
+            origin: octree.voxel_to_world(voxel) + half_size,
+            direction: vec3(0.3, -0.5, 0.8).normalize(),
+        };
+
+        let expected = ExpectedHit {
+            distance: 0.0,
+            normal: Vec3::ZERO,
+            voxel,
+            attribute,
+        };
+        assert_hit(&ray, &octree, &expected);
+    }
+}
+
+#[test]
+fn test_trace_octree_stats() {
+    use glam::IVec3;
+
+    // Voxels in opposite corners of a 8^3 grid
+    let voxels = [IVec3::ZERO, IVec3::splat(7)].map(|voxel| (voxel, crate::VoxelAttribute(1)));
+    let octree = crate::Octree::from_voxel_attributes(voxels.into_iter()).unwrap();
+    let ray = |origin: Vec3| Ray {
+        origin,
+        direction: Vec3::X,
+    };
+
+    // A hit uses the stack down to the level of the voxel
+    let center = octree.voxel_to_world(IVec3::ZERO) + octree.voxel_size() / 2.0;
+    let (hit, stats) = trace_octree_stats(&ray(center - Vec3::X * 2.0), &octree.nodes);
+    assert_eq!(
+        hit,
+        trace_octree_hit(&ray(center - Vec3::X * 2.0), &octree.nodes)
+    );
+    assert_eq!(stats.max_stack_depth, octree.depth);
+    assert!(stats.iterations >= octree.depth);
+
+    // Rays missing the octree do no work
+    let (hit, stats) = trace_octree_stats(&ray(Vec3::Y * 2.0), &octree.nodes);
+    assert_eq!((hit, stats), (OctreeHit::MISS, TraversalStats::default()));
 
-    // Color based on child index
-    vec3(
-        ((child_idx ^ dir_mask) & 1) as f32,
-        (((child_idx ^ dir_mask) & 2) >> 1) as f32,
-        (((child_idx ^ dir_mask) & 4) >> 2) as f32,
-    )
+    // Rays passing next to a voxel descend without hitting it
+    let (hit, stats) = trace_octree_stats(&ray(vec3(-2.0, -0.9, -0.5)), &octree.nodes);
+    assert_eq!(hit, OctreeHit::MISS);
+    assert!(
+        stats.iterations > 2 && stats.max_stack_depth == 2,
+        "{stats:?}"
+    );
 }
@@ -0,0 +1,245 @@
+use crate::{
+    reflect_metal, scatter_glass, sky_color, Material, OctreeNode, OctreeTraversal, Random, Ray,
+    Sky, Sun, VoxelAttribute,
+};
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use glam::{Vec4, Vec4Swizzles};
+
+/// State of the progressive path tracer, laid out to match the uniform block of the shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Zeroable, Pod)]
+pub struct PathTracing {
+    /// Number of frames accumulated so far, zero restarts the accumulation
+    pub frame: u32,
+    /// Maximum number of diffuse bounces after the first hit
+    pub bounces: u32,
+}
+
+impl Default for PathTracing {
+    fn default() -> Self {
+        Self {
+            frame: 0,
+            bounces: 4,
+        }
+    }
+}
+
+/// Radiance arriving along a ray, estimated by a single path of bounces.
+/// The sun is sampled at every diffuse hit with a shadow ray, the sky lights escaping paths.
+/// Metal and glass voxels reflect and refract the path.
+#[allow(clippy::too_many_arguments)]
+pub fn trace_path(
+    ray: &Ray,
+    octree: &[OctreeNode],
+    attributes: &[VoxelAttribute],
+    materials: &[Material],
+    sun: &Sun,
+    sky: &Sky,
+    environment: &[Vec4],
+    bounces: u32,
+    random: &mut Random,
+    traversal: impl OctreeTraversal,
+) -> Vec3 {
+    let mut radiance = Vec3::ZERO;
+    let mut throughput = Vec3::ONE;
+    let mut ray = Ray {
+        origin: ray.origin,
+        direction: ray.direction,
+    };
+
+    let mut bounce = 0;
+    while bounce <= bounces {
+        let hit = traversal.trace(&ray, octree);
+        if !hit.is_hit() {
+            radiance += throughput * sky_color(&ray, sky, sun, environment);
+            break;
+        }
+
+        let attribute = hit.attribute(octree, attributes);
+        let albedo = attribute.color();
+        let material = Material::of(attribute, materials);
+        let origin = hit.position + hit.normal * hit.voxel_size() * 1e-3;
+
+        if material.kind == Material::METAL {
+            throughput *= albedo;
+            let direction = reflect_metal(ray.direction, hit.normal, &material, random);
+            if direction == Vec3::ZERO {
+                break;
+            }
+            ray = Ray { origin, direction };
+            bounce += 1;
+            continue;
+        }
+
+        if material.kind == Material::GLASS {
+            throughput *= albedo;
+            ray = scatter_glass(
+                &ray, &hit, &material, octree, attributes, materials, random, traversal,
+            );
+            if ray.direction == Vec3::ZERO {
+                break;
+            }
+            bounce += 1;
+            continue;
+        }
+
+        radiance += throughput * albedo * material.emission;
+
+        // Direct light of the sun
+        let lambert = hit.normal.dot(sun.direction.xyz()).max(0.0);
+        if lambert > 0.0 {
+            let shadow_ray = Ray {
+                origin,
+                direction: sun.direction.xyz(),
+            };
+            if !traversal.trace(&shadow_ray, octree).is_hit() {
+                radiance += throughput * albedo * sun.color.xyz() * sun.intensity * lambert;
+            }
+        }
+
+        // Cosine weighted sampling cancels the cosine and pdf of the diffuse surface
+        throughput *= albedo;
+        ray = Ray {
+            origin,
+            direction: random.cosine_direction(hit.normal),
+        };
+        bounce += 1;
+    }
+
+    radiance
+}
+
+#[test]
+fn test_trace_path() {
+    use crate::{Octree, StackTraversal};
+    use glam::IVec3;
+
+    // A floor with a roof floating above its center
+    let floor = (0..16 * 16).map(|i| IVec3::new(i % 16, 0, i / 16));
+    let roof = (0..8 * 8).map(|i| IVec3::new(4 + i % 8, 8, 4 + i / 8));
+    let attribute = VoxelAttribute::new([200, 200, 200], 1);
+    let octree =
+        Octree::from_voxel_attributes(floor.chain(roof).map(|voxel| (voxel, attribute))).unwrap();
+
+    let materials = [Material::diffuse(); 256];
+    let sun = Sun {
+        direction: Vec4::Y,
+        ..Sun::default()
+    };
+
+    let mut random = Random::new(3);
+    let mut radiance_below = |voxel: IVec3, bounces: u32, samples: u32| {
+        let ray = Ray {
+            origin: octree.voxel_to_world(voxel) + octree.voxel_size() / 2.0,
+            direction: -Vec3::Y,
+        };
+
+        let mut radiance = Vec3::ZERO;
+        for _ in 0..samples {
+            radiance += trace_path(
+                &ray,
+                &octree.nodes,
+                &octree.attributes,
+                &materials,
+                &sun,
+                &Sky::default(),
+                &[],
+                bounces,
+                &mut random,
+                StackTraversal,
+            );
+        }
+        radiance / samples as f32
+    };
+
+    // Without bounces only the sun lights the first hit
+    let albedo = attribute.color();
+    let lit = radiance_below(IVec3::new(1, 4, 1), 0, 1);
+    assert!((lit - albedo * sun.color.xyz() * sun.intensity).length() < 1e-6);
+    assert_eq!(radiance_below(IVec3::new(8, 4, 8), 0, 1), Vec3::ZERO);
+
+    // Bounces bring light from the sky and lit floor below the roof
+    let shadowed = radiance_below(IVec3::new(8, 4, 8), 4, 256);
+    assert!(shadowed.min_element() > 0.02, "{shadowed}");
+    assert!(shadowed.dot(Vec3::ONE) < lit.dot(Vec3::ONE));
+
+    // Rays missing the octree see the sky
+    let ray = Ray {
+        origin: Vec3::splat(3.0),
+        direction: Vec3::ONE.normalize(),
+    };
+    let sky = trace_path(
+        &ray,
+        &octree.nodes,
+        &octree.attributes,
+        &materials,
+        &sun,
+        &Sky::default(),
+        &[],
+        4,
+        &mut random,
+        StackTraversal,
+    );
+    assert_eq!(sky, sky_color(&ray, &Sky::default(), &sun, &[]));
+}
+
+#[test]
+fn test_trace_path_materials() {
+    use crate::{reflect, Octree, StackTraversal};
+    use glam::IVec3;
+
+    // A floor of metal and emissive stripes
+    let floor = (0..16 * 16).map(|i| {
+        let voxel = IVec3::new(i % 16, 0, i / 16);
+        let palette_index = 1 + (voxel.x / 8) as u8;
+        (voxel, VoxelAttribute::new([255, 128, 64], palette_index))
+    });
+    let octree = Octree::from_voxel_attributes(floor).unwrap();
+
+    let mut materials = [Material::diffuse(); 256];
+    materials[1] = Material::metal(0.0);
+    materials[2] = Material::emissive(2.0);
+
+    // The sun shines from below
+    let sun = Sun {
+        direction: -Vec4::Y,
+        ..Sun::default()
+    };
+
+    let mut random = Random::new(5);
+    let mut trace_towards = |voxel: IVec3, bounces: u32| {
+        let target = octree.voxel_to_world(voxel) + octree.voxel_size() * 0.5;
+        let ray = Ray {
+            origin: target + Vec3::new(-0.2, 0.5, 0.0),
+            direction: Vec3::new(0.2, -0.5, 0.0).normalize(),
+        };
+        let radiance = trace_path(
+            &ray,
+            &octree.nodes,
+            &octree.attributes,
+            &materials,
+            &sun,
+            &Sky::default(),
+            &[],
+            bounces,
+            &mut random,
+            StackTraversal,
+        );
+        (ray, radiance)
+    };
+
+    // A mirror shows the tinted sky
+    let albedo = VoxelAttribute::new([255, 128, 64], 1).color();
+    let (ray, mirror) = trace_towards(IVec3::new(4, 0, 8), 1);
+    let reflected = Ray {
+        origin: Vec3::ZERO,
+        direction: reflect(ray.direction, Vec3::Y),
+    };
+    let sky = sky_color(&reflected, &Sky::default(), &sun, &[]);
+    assert!((mirror - albedo * sky).length() < 1e-5);
+
+    // Emissive voxels glow without any light
+    let (_, glow) = trace_towards(IVec3::new(12, 0, 8), 0);
+    assert!((glow - albedo * 2.0).length() < 1e-5);
+}
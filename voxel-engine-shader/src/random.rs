@@ -0,0 +1,74 @@
+use core::f32::consts::PI;
+use glam::Vec3;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+
+/// Scramble the bits of a value with the PCG hash
+pub fn hash(value: u32) -> u32 {
+    permute(value.wrapping_mul(747796405).wrapping_add(2891336453))
+}
+
+/// Output permutation of PCG, turning an LCG state into a random number
+fn permute(state: u32) -> u32 {
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// Small random number generator, seeded per pixel
+pub struct Random {
+    state: u32,
+}
+
+impl Random {
+    pub fn new(seed: u32) -> Self {
+        Self { state: hash(seed) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state = self.state.wrapping_mul(747796405).wrapping_add(2891336453);
+        permute(self.state)
+    }
+
+    /// Uniform random number in `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Random direction, uniformly distributed on the unit sphere
+    pub fn unit_vector(&mut self) -> Vec3 {
+        let z = 1.0 - 2.0 * self.next_f32();
+        let phi = 2.0 * PI * self.next_f32();
+        let radius = (1.0 - z * z).sqrt();
+
+        Vec3::new(radius * phi.cos(), radius * phi.sin(), z)
+    }
+
+    /// Random direction in the hemisphere around the normal, more likely close to the normal
+    pub fn cosine_direction(&mut self, normal: Vec3) -> Vec3 {
+        let (tangent, bitangent) = normal.any_orthonormal_pair();
+
+        let u = self.next_f32();
+        let phi = 2.0 * PI * self.next_f32();
+        let radius = u.sqrt();
+
+        (tangent * phi.cos() + bitangent * phi.sin()) * radius + normal * (1.0 - u).sqrt()
+    }
+}
+
+#[test]
+fn test_cosine_direction() {
+    let mut random = Random::new(42);
+
+    for normal in [Vec3::X, -Vec3::Y, Vec3::new(1.0, 2.0, -3.0).normalize()] {
+        let mut mean = Vec3::ZERO;
+        for _ in 0..2000 {
+            let direction = random.cosine_direction(normal);
+            assert!((direction.length() - 1.0).abs() < 1e-4);
+            assert!(direction.dot(normal) >= 0.0);
+            mean += direction / 2000.0;
+        }
+
+        // The mean cosine of a cosine weighted hemisphere is 2/3
+        assert!((mean - normal * 2.0 / 3.0).length() < 0.03, "{mean}");
+    }
+}
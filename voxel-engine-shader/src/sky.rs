@@ -1,7 +1,196 @@
 use crate::ray::Ray;
-use glam::Vec3;
+use crate::Sun;
+use bytemuck::{Pod, Zeroable};
+use core::f32::consts::PI;
+use glam::{vec3, Mat3, Vec3, Vec4, Vec4Swizzles};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
 
-pub fn sky_color(ray: &Ray) -> Vec3 {
-    let t = 0.5 * (ray.direction.y + 1.0);
-    return (1.0 - t) * Vec3::splat(1.0) + t * Vec3::new(0.5, 0.7, 1.0);
+/// Lighting of rays leaving the octree, laid out to match the uniform block of the shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Zeroable, Pod)]
+pub struct Sky {
+    /// One of the `Sky::*` models
+    pub model: u32,
+    /// Haziness of the physical sky, 2 is a clear and 10 a hazy day
+    pub turbidity: f32,
+    /// Scale of the sky radiance
+    pub intensity: f32,
+    /// Width of the equirectangular environment map in pixels
+    pub width: u32,
+    /// Height of the equirectangular environment map in pixels
+    pub height: u32,
+}
+
+impl Sky {
+    /// Analytic daylight sky of Preetham et al., lit by the sun
+    pub const PHYSICAL: u32 = 0;
+    /// Equirectangular environment map
+    pub const ENVIRONMENT: u32 = 1;
+
+    pub fn environment(width: u32, height: u32) -> Self {
+        Self {
+            model: Self::ENVIRONMENT,
+            intensity: 1.0,
+            width,
+            height,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            model: Self::PHYSICAL,
+            turbidity: 2.5,
+            intensity: 0.05,
+            width: 0,
+            height: 0,
+        }
+    }
+}
+
+/// Perez luminance distribution of the sky for the zenith angle and the angle to the sun
+fn perez(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    let cos_gamma = gamma.cos();
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+/// Radiance of the Preetham sky in linear sRGB, the luminance is in kcd/m²
+pub fn physical_sky(direction: Vec3, sun_direction: Vec3, turbidity: f32) -> Vec3 {
+    let t = turbidity;
+
+    // Directions below the horizon see the horizon, a sun below it sets
+    let cos_theta = direction.y.max(0.01);
+    let direction = vec3(direction.x, cos_theta, direction.z).normalize();
+    let sun_direction =
+        vec3(sun_direction.x, sun_direction.y.max(0.01), sun_direction.z).normalize();
+    let theta_sun = sun_direction.y.acos();
+    let gamma = direction.dot(sun_direction).clamp(-1.0, 1.0).acos();
+
+    let luminance = [
+        0.1787 * t - 1.4630,
+        -0.3554 * t + 0.4275,
+        -0.0227 * t + 5.3251,
+        0.1206 * t - 2.5771,
+        -0.0670 * t + 0.3703,
+    ];
+    let x = [
+        -0.0193 * t - 0.2592,
+        -0.0665 * t + 0.0008,
+        -0.0004 * t + 0.2125,
+        -0.0641 * t - 0.8989,
+        -0.0033 * t + 0.0452,
+    ];
+    let y = [
+        -0.0167 * t - 0.2608,
+        -0.0950 * t + 0.0092,
+        -0.0079 * t + 0.2102,
+        -0.0441 * t - 1.6537,
+        -0.0109 * t + 0.0529,
+    ];
+
+    // Values at the zenith
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+    let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let thetas = Vec4::new(theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0);
+    let turbidities = vec3(t * t, t, 1.0);
+    let zenith_x = turbidities.dot(vec3(
+        thetas.dot(Vec4::new(0.00166, -0.00375, 0.00209, 0.0)),
+        thetas.dot(Vec4::new(-0.02903, 0.06377, -0.03202, 0.00394)),
+        thetas.dot(Vec4::new(0.11693, -0.21196, 0.06052, 0.25886)),
+    ));
+    let zenith_y = turbidities.dot(vec3(
+        thetas.dot(Vec4::new(0.00275, -0.00610, 0.00317, 0.0)),
+        thetas.dot(Vec4::new(-0.04214, 0.08970, -0.04153, 0.00516)),
+        thetas.dot(Vec4::new(0.15346, -0.26756, 0.06670, 0.26688)),
+    ));
+
+    // Scale the zenith values by the distribution relative to the zenith
+    let sky_luminance =
+        zenith_luminance * perez(&luminance, cos_theta, gamma) / perez(&luminance, 1.0, theta_sun);
+    let sky_x = zenith_x * perez(&x, cos_theta, gamma) / perez(&x, 1.0, theta_sun);
+    let sky_y = zenith_y * perez(&y, cos_theta, gamma) / perez(&y, 1.0, theta_sun);
+
+    // xyY to XYZ to linear sRGB
+    let xyz = vec3(
+        sky_x * sky_luminance / sky_y,
+        sky_luminance,
+        (1.0 - sky_x - sky_y) * sky_luminance / sky_y,
+    );
+    let xyz_to_rgb = Mat3::from_cols(
+        vec3(3.2406, -0.9689, 0.0557),
+        vec3(-1.5372, 1.8758, -0.2040),
+        vec3(-0.4986, 0.0415, 1.0570),
+    );
+
+    (xyz_to_rgb * xyz).max(Vec3::ZERO)
+}
+
+/// Look up an equirectangular environment map, its center column faces -z
+pub fn sample_environment(direction: Vec3, sky: &Sky, environment: &[Vec4]) -> Vec3 {
+    let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+    let x = ((u * sky.width as f32) as u32).min(sky.width - 1);
+    let y = ((v * sky.height as f32) as u32).min(sky.height - 1);
+
+    environment[(y * sky.width + x) as usize].xyz()
+}
+
+/// Radiance of the sky seen along a ray
+pub fn sky_color(ray: &Ray, sky: &Sky, sun: &Sun, environment: &[Vec4]) -> Vec3 {
+    let radiance = if sky.model == Sky::ENVIRONMENT {
+        sample_environment(ray.direction, sky, environment)
+    } else {
+        physical_sky(ray.direction, sun.direction.xyz(), sky.turbidity)
+    };
+
+    radiance * sky.intensity
+}
+
+#[test]
+fn test_physical_sky() {
+    let sun = vec3(0.0, 0.5, -1.0).normalize();
+    let sky = |direction: Vec3| physical_sky(direction.normalize(), sun, 2.5);
+
+    // A clear sky is blue, brightest around the sun and towards the horizon
+    let zenith = sky(Vec3::Y);
+    assert!(zenith.z > zenith.x && zenith.z > 1.0, "{zenith}");
+    assert!(sky(sun).y > zenith.y);
+    assert!(sky(vec3(0.0, 0.05, 1.0)).y > sky(vec3(0.0, 0.6, 1.0)).y);
+
+    // The sky darkens as the sun sets
+    let noon = physical_sky(Vec3::Y, vec3(0.0, 1.0, -0.2).normalize(), 2.5);
+    let sunset = physical_sky(Vec3::Y, vec3(0.0, 0.05, -1.0).normalize(), 2.5);
+    assert!(sunset.y < noon.y * 0.5, "{sunset} {noon}");
+
+    // Directions below the horizon and night time stay finite
+    assert!(sky(-Vec3::Y).is_finite());
+    assert!(physical_sky(Vec3::Y, -Vec3::Y, 2.5).is_finite());
+}
+
+#[test]
+fn test_sample_environment() {
+    // A 4x2 map with a distinct color per pixel
+    let environment = (0..8)
+        .map(|i| Vec4::new(i as f32, 0.0, 0.0, 1.0))
+        .collect::<alloc::vec::Vec<_>>();
+    let sky = Sky::environment(4, 2);
+    let sample = |direction: Vec3| sample_environment(direction.normalize(), &sky, &environment).x;
+
+    assert_eq!(sample(vec3(-0.1, 1.0, -1.0)), 1.0);
+    assert_eq!(sample(vec3(0.1, 1.0, -1.0)), 2.0);
+    assert_eq!(sample(vec3(0.1, -1.0, -1.0)), 6.0);
+    assert_eq!(sample(vec3(-1.0, 0.1, 0.01)), 0.0);
+    assert_eq!(sample(vec3(1.0, 0.1, 0.01)), 3.0);
+
+    let ray = Ray {
+        origin: Vec3::ZERO,
+        direction: vec3(0.1, -1.0, -1.0).normalize(),
+    };
+    let sun = Sun::default();
+    assert_eq!(sky_color(&ray, &sky, &sun, &environment), Vec3::X * 6.0);
 }
@@ -9,6 +9,12 @@ pub struct Stack {
     ptr: i32,
 }
 
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Stack {
     pub fn new() -> Self {
         Self {